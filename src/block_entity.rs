@@ -0,0 +1,523 @@
+//! Typed representations of the block entities that are stored in chunks.
+//!
+//! Block entities are parsed based on their `id`, so that the data for common block entities
+//! (such as chests, furnaces and signs) can be accessed through structs.  Block entities with an
+//! unknown id (such as those added by mods), or which could not be parsed as the type for their
+//! id, are kept as [`BlockEntity::Unknown`] so that none of their data is lost.
+//!
+//! The fields which a typed block entity doesn't model are kept in its `extra` map, so that only
+//! [`BlockEntity::Unknown`] keeps the nbt exactly as it was saved.  Items which are not valid item
+//! stacks (such as those with the numeric ids used before 1.8) are left out of the items of a
+//! block entity, rather than making the whole block entity unknown.  Block entities are not
+//! serialized back into nbt by this crate.
+//!
+//! - See <https://minecraft.wiki/w/Chunk_format#Block_entity_format>
+
+use std::{borrow::Cow, collections::HashMap};
+
+use fastnbt::Value;
+use serde::Deserialize;
+
+use crate::{
+    item::{deserialize_item, deserialize_items, ItemStack},
+    nbt::{Namespace, NamespacedKey},
+};
+
+/// Create a struct for a block entity, with the fields which are common to all block entities
+/// followed by the given fields
+macro_rules! block_entity {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident: $ty:ty,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Deserialize, Debug, Clone, PartialEq)]
+        pub struct $name {
+            /// The id of the block entity
+            pub id: NamespacedKey,
+            /// The x position of the block entity (absolute, __not__ relative to the chunk)
+            pub x: i32,
+            /// The y position of the block entity
+            pub y: i32,
+            /// The z position of the block entity (absolute, __not__ relative to the chunk)
+            pub z: i32,
+            /// If true, this is an invalid block entity, and this block is not immediately placed
+            /// when a loaded chunk is loaded
+            #[serde(rename = "keepPacked", default)]
+            pub keep_packed: bool,
+            $(
+                $(#[$field_meta])*
+                pub $field: $ty,
+            )*
+            /// The fields of this block entity which are not modelled by this struct (i.e. the
+            /// `components` of the block entity)
+            #[serde(flatten)]
+            pub extra: HashMap<String, Value>,
+        }
+    };
+}
+
+block_entity! {
+    /// A block entity which stores items and can have a loot table, used for chests, trapped
+    /// chests, barrels, shulker boxes, dispensers and droppers.
+    Container {
+        /// The name of this container, as a text component
+        #[serde(rename = "CustomName")]
+        custom_name: Option<Value>,
+        /// The items in this container
+        #[serde(rename = "Items", default, deserialize_with = "deserialize_items")]
+        items: Vec<ItemStack>,
+        /// The lock on this container, if any
+        #[serde(rename = "Lock")]
+        lock: Option<Value>,
+        /// The loot table which will fill this container when it is next opened
+        #[serde(rename = "LootTable")]
+        loot_table: Option<String>,
+        /// The seed of the loot table
+        #[serde(rename = "LootTableSeed")]
+        loot_table_seed: Option<i64>,
+    }
+}
+
+block_entity! {
+    /// A hopper
+    Hopper {
+        /// The name of this hopper, as a text component
+        #[serde(rename = "CustomName")]
+        custom_name: Option<Value>,
+        /// The items in this hopper
+        #[serde(rename = "Items", default, deserialize_with = "deserialize_items")]
+        items: Vec<ItemStack>,
+        /// The lock on this hopper, if any
+        #[serde(rename = "Lock")]
+        lock: Option<Value>,
+        /// The loot table which will fill this hopper when it is next opened
+        #[serde(rename = "LootTable")]
+        loot_table: Option<String>,
+        /// The seed of the loot table
+        #[serde(rename = "LootTableSeed")]
+        loot_table_seed: Option<i64>,
+        /// Ticks until the next item is transferred
+        #[serde(rename = "TransferCooldown", default)]
+        transfer_cooldown: i32,
+    }
+}
+
+block_entity! {
+    /// A furnace, blast furnace or smoker
+    Furnace {
+        /// The name of this furnace, as a text component
+        #[serde(rename = "CustomName")]
+        custom_name: Option<Value>,
+        /// The items in this furnace: the input (slot 0), fuel (slot 1) and output (slot 2)
+        #[serde(rename = "Items", default, deserialize_with = "deserialize_items")]
+        items: Vec<ItemStack>,
+        /// The lock on this furnace, if any
+        #[serde(rename = "Lock")]
+        lock: Option<Value>,
+        /// Number of ticks left before the current fuel runs out
+        #[serde(rename = "BurnTime", alias = "lit_time_remaining", default)]
+        burn_time: i16,
+        /// Number of ticks the item has been smelting for
+        #[serde(rename = "CookTime", alias = "cooking_time_spent", default)]
+        cook_time: i16,
+        /// Number of ticks it takes for the item to be smelted
+        #[serde(rename = "CookTimeTotal", alias = "cooking_total_time", default)]
+        cook_time_total: i16,
+        /// The recipes that have been used since the last time experience was collected
+        #[serde(rename = "RecipesUsed")]
+        recipes_used: Option<Value>,
+    }
+}
+
+block_entity! {
+    /// A sign or a hanging sign
+    Sign {
+        /// The text on the front of the sign (since 1.20)
+        front_text: Option<Value>,
+        /// The text on the back of the sign (since 1.20)
+        back_text: Option<Value>,
+        /// Whether the sign has been waxed (since 1.20)
+        is_waxed: Option<bool>,
+        /// The lines of text on the sign, as text components (before 1.20)
+        #[serde(rename = "Text1")]
+        text1: Option<String>,
+        /// The second line of text (before 1.20)
+        #[serde(rename = "Text2")]
+        text2: Option<String>,
+        /// The third line of text (before 1.20)
+        #[serde(rename = "Text3")]
+        text3: Option<String>,
+        /// The fourth line of text (before 1.20)
+        #[serde(rename = "Text4")]
+        text4: Option<String>,
+    }
+}
+
+block_entity! {
+    /// A banner (standing or on a wall)
+    Banner {
+        /// The name of this banner, as a text component
+        #[serde(rename = "CustomName")]
+        custom_name: Option<Value>,
+        /// The patterns on this banner
+        #[serde(rename = "Patterns", alias = "patterns", default)]
+        patterns: Vec<Value>,
+    }
+}
+
+block_entity! {
+    /// A monster spawner
+    Spawner {
+        /// Ticks until the next spawn
+        #[serde(rename = "Delay", default)]
+        delay: i16,
+        /// The minimum random delay for the next spawn
+        #[serde(rename = "MinSpawnDelay")]
+        min_spawn_delay: Option<i16>,
+        /// The maximum random delay for the next spawn
+        #[serde(rename = "MaxSpawnDelay")]
+        max_spawn_delay: Option<i16>,
+        /// How many mobs to attempt to spawn each time
+        #[serde(rename = "SpawnCount")]
+        spawn_count: Option<i16>,
+        /// The maximum number of nearby entities, above which no more are spawned
+        #[serde(rename = "MaxNearbyEntities")]
+        max_nearby_entities: Option<i16>,
+        /// The distance a player has to be within for the spawner to be active
+        #[serde(rename = "RequiredPlayerRange")]
+        required_player_range: Option<i16>,
+        /// The radius around the spawner that mobs can be spawned in
+        #[serde(rename = "SpawnRange")]
+        spawn_range: Option<i16>,
+        /// The entity that will be spawned next
+        #[serde(rename = "SpawnData")]
+        spawn_data: Option<Value>,
+        /// The weighted list of entities that can be spawned
+        #[serde(rename = "SpawnPotentials", default)]
+        spawn_potentials: Vec<Value>,
+    }
+}
+
+block_entity! {
+    /// A beacon
+    Beacon {
+        /// The name of this beacon, as a text component
+        #[serde(rename = "CustomName")]
+        custom_name: Option<Value>,
+        /// The lock on this beacon, if any
+        #[serde(rename = "Lock")]
+        lock: Option<Value>,
+        /// The number of levels of the pyramid below the beacon
+        #[serde(rename = "Levels", default)]
+        levels: i32,
+        /// The primary effect, as an id (since 1.20.2) or a number (before that)
+        #[serde(alias = "Primary")]
+        primary_effect: Option<Value>,
+        /// The secondary effect, as an id (since 1.20.2) or a number (before that)
+        #[serde(alias = "Secondary")]
+        secondary_effect: Option<Value>,
+    }
+}
+
+block_entity! {
+    /// A lectern
+    Lectern {
+        /// The book on this lectern, if any
+        #[serde(rename = "Book", default, deserialize_with = "deserialize_item")]
+        book: Option<ItemStack>,
+        /// The page that the book is open to
+        #[serde(rename = "Page")]
+        page: Option<i32>,
+    }
+}
+
+block_entity! {
+    /// A brewing stand
+    BrewingStand {
+        /// The name of this brewing stand, as a text component
+        #[serde(rename = "CustomName")]
+        custom_name: Option<Value>,
+        /// The items in this brewing stand: the potions (slots 0-2), ingredient (slot 3) and fuel
+        /// (slot 4)
+        #[serde(rename = "Items", default, deserialize_with = "deserialize_items")]
+        items: Vec<ItemStack>,
+        /// The lock on this brewing stand, if any
+        #[serde(rename = "Lock")]
+        lock: Option<Value>,
+        /// The number of ticks that the potions have to brew for
+        #[serde(rename = "BrewTime", default)]
+        brew_time: i16,
+        /// The remaining fuel for the brewing stand
+        #[serde(rename = "Fuel", default)]
+        fuel: i8,
+    }
+}
+
+block_entity! {
+    /// A campfire or soul campfire
+    Campfire {
+        /// The items cooking on this campfire
+        #[serde(rename = "Items", default, deserialize_with = "deserialize_items")]
+        items: Vec<ItemStack>,
+        /// How long each item has been cooking for
+        #[serde(rename = "CookingTimes")]
+        cooking_times: Option<Value>,
+        /// How long each item has to cook for
+        #[serde(rename = "CookingTotalTimes")]
+        cooking_total_times: Option<Value>,
+    }
+}
+
+/// A block entity in a chunk
+///
+/// Each variant (except [`BlockEntity::Unknown`]) holds the id, position and `keepPacked` fields
+/// that are common to all block entities, which can also be accessed through methods on this
+/// enum.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockEntity {
+    /// A chest or trapped chest
+    Chest(Container),
+    /// A barrel
+    Barrel(Container),
+    /// A shulker box of any colour
+    ShulkerBox(Container),
+    /// A dispenser or dropper
+    Dispenser(Container),
+    /// A hopper
+    Hopper(Hopper),
+    /// A furnace, blast furnace or smoker
+    Furnace(Furnace),
+    /// A sign or hanging sign
+    Sign(Sign),
+    /// A banner
+    Banner(Banner),
+    /// A monster spawner
+    Spawner(Spawner),
+    /// A beacon
+    Beacon(Beacon),
+    /// A lectern
+    Lectern(Lectern),
+    /// A brewing stand
+    BrewingStand(BrewingStand),
+    /// A campfire or soul campfire
+    Campfire(Campfire),
+    /// A block entity with an unknown id, or which could not be parsed as the type for its id
+    Unknown(Value),
+}
+
+/// Call `$f` with the struct in any of the typed variants of [`BlockEntity`], or evaluate
+/// `$unknown` with the value of [`BlockEntity::Unknown`]
+macro_rules! with_block_entity {
+    ($self: expr, $be: ident => $f: expr, $value: ident => $unknown: expr) => {
+        match $self {
+            BlockEntity::Chest($be)
+            | BlockEntity::Barrel($be)
+            | BlockEntity::ShulkerBox($be)
+            | BlockEntity::Dispenser($be) => $f,
+            BlockEntity::Hopper($be) => $f,
+            BlockEntity::Furnace($be) => $f,
+            BlockEntity::Sign($be) => $f,
+            BlockEntity::Banner($be) => $f,
+            BlockEntity::Spawner($be) => $f,
+            BlockEntity::Beacon($be) => $f,
+            BlockEntity::Lectern($be) => $f,
+            BlockEntity::BrewingStand($be) => $f,
+            BlockEntity::Campfire($be) => $f,
+            BlockEntity::Unknown($value) => $unknown,
+        }
+    };
+}
+
+impl BlockEntity {
+    /// Parse a block entity from its nbt, based on its id
+    pub fn from_value(value: Value) -> Self {
+        let Some(id) = get(&value, "id").and_then(|id| match id {
+            Value::String(s) => Some(NamespacedKey::from(s.as_str())),
+            _ => None,
+        }) else {
+            return Self::Unknown(value);
+        };
+
+        let parsed = if id.namespace != Namespace::Minecraft {
+            None
+        } else {
+            match id.key.as_str() {
+                "chest" | "trapped_chest" => parse(&value, Self::Chest),
+                "barrel" => parse(&value, Self::Barrel),
+                "dispenser" | "dropper" => parse(&value, Self::Dispenser),
+                k if k.ends_with("shulker_box") => parse(&value, Self::ShulkerBox),
+                "hopper" => parse(&value, Self::Hopper),
+                "furnace" | "blast_furnace" | "smoker" => parse(&value, Self::Furnace),
+                "sign" | "hanging_sign" => parse(&value, Self::Sign),
+                "banner" => parse(&value, Self::Banner),
+                "mob_spawner" => parse(&value, Self::Spawner),
+                "beacon" => parse(&value, Self::Beacon),
+                "lectern" => parse(&value, Self::Lectern),
+                "brewing_stand" => parse(&value, Self::BrewingStand),
+                "campfire" => parse(&value, Self::Campfire),
+                _ => None,
+            }
+        };
+
+        parsed.unwrap_or(Self::Unknown(value))
+    }
+
+    /// Get the id of this block entity.  Returns [`None`] if this is an unknown block entity
+    /// without a string `id`.
+    pub fn id(&self) -> Option<NamespacedKey> {
+        with_block_entity!(self, be => Some(be.id.clone()), value => match get(value, "id")? {
+            Value::String(s) => Some(NamespacedKey::from(s.as_str())),
+            _ => None,
+        })
+    }
+
+    /// Get the position of this block entity as absolute (x, y, z) coordinates.  Returns [`None`]
+    /// if this is an unknown block entity without a position.
+    pub fn position(&self) -> Option<(i32, i32, i32)> {
+        with_block_entity!(self, be => Some((be.x, be.y, be.z)), value => Some((
+            get_int(value, "x")?,
+            get_int(value, "y")?,
+            get_int(value, "z")?,
+        )))
+    }
+
+    /// Get the items stored in this block entity, or an empty slice if it doesn't store items.
+    ///
+    /// For unknown block entities (such as chests from before 1.11, whose id was `Chest`), the
+    /// items are read from their `Items` list, so items which are stored anywhere else (i.e. by
+    /// modded block entities) are not included.  Items which are not valid item stacks are
+    /// skipped.
+    ///
+    /// Items inside of these items (such as a shulker box in a chest) can be found with
+    /// [`ItemStack::contents_recursive`].
+    pub fn items(&self) -> Cow<'_, [ItemStack]> {
+        match self {
+            BlockEntity::Chest(c)
+            | BlockEntity::Barrel(c)
+            | BlockEntity::ShulkerBox(c)
+            | BlockEntity::Dispenser(c) => Cow::Borrowed(&c.items),
+            BlockEntity::Hopper(h) => Cow::Borrowed(&h.items),
+            BlockEntity::Furnace(f) => Cow::Borrowed(&f.items),
+            BlockEntity::BrewingStand(b) => Cow::Borrowed(&b.items),
+            BlockEntity::Campfire(c) => Cow::Borrowed(&c.items),
+            BlockEntity::Lectern(l) => Cow::Borrowed(l.book.as_slice()),
+            BlockEntity::Unknown(value) => match get(value, "Items") {
+                Some(Value::List(list)) => list.iter().filter_map(ItemStack::from_value).collect(),
+                _ => Cow::Borrowed(&[]),
+            },
+            _ => Cow::Borrowed(&[]),
+        }
+    }
+
+    /// Check if this block entity is invalid and should not be placed when the chunk is loaded
+    pub fn keep_packed(&self) -> bool {
+        with_block_entity!(self, be => be.keep_packed, value => {
+            matches!(get(value, "keepPacked"), Some(Value::Byte(1)))
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockEntity {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(Self::from_value(Value::deserialize(deserializer)?))
+    }
+}
+
+/// Parse `value` as `T`, and wrap it in a variant of [`BlockEntity`]
+fn parse<'a, T>(value: &'a Value, variant: fn(T) -> BlockEntity) -> Option<BlockEntity>
+where
+    T: Deserialize<'a>,
+{
+    fastnbt::from_value(value).ok().map(variant)
+}
+
+/// Get a field of a compound
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Compound(c) => c.get(key),
+        _ => None,
+    }
+}
+
+/// Get an int field of a compound
+fn get_int(value: &Value, key: &str) -> Option<i32> {
+    match get(value, key)? {
+        Value::Int(n) => Some(*n),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_block_entity() {
+    let chest = BlockEntity::from_value(fastnbt::nbt!({
+        "id": "minecraft:chest",
+        "x": 10,
+        "y": -3,
+        "z": 20,
+        "keepPacked": 0i8,
+        "custom": 5,
+        "Items": [
+            { "Slot": 0i8, "id": "minecraft:stone", "Count": 3i8 },
+            // Items which are not valid are skipped, instead of the whole chest failing to parse
+            { "Slot": 1i8, "id": 1i16, "Count": 1i8 },
+        ],
+    }));
+    let BlockEntity::Chest(c) = &chest else {
+        panic!("expected a chest, got {:?}", chest);
+    };
+    assert_eq!(c.items.len(), 1);
+    assert_eq!(chest.items()[0].id, "stone".into());
+    assert_eq!(chest.items()[0].count, 3);
+    assert_eq!(chest.position(), Some((10, -3, 20)));
+    assert_eq!(chest.id(), Some("chest".into()));
+    assert!(!chest.keep_packed());
+    // Fields which are not modelled are kept, rather than dropped
+    assert_eq!(c.extra.len(), 1);
+    assert_eq!(c.extra["custom"], Value::Int(5));
+
+    let furnace = BlockEntity::from_value(fastnbt::nbt!({
+        "id": "minecraft:blast_furnace",
+        "x": 0,
+        "y": 0,
+        "z": 0,
+        "BurnTime": 100i16,
+    }));
+    assert!(matches!(
+        furnace,
+        BlockEntity::Furnace(Furnace { burn_time: 100, .. })
+    ));
+
+    // Block entities from mods and those that fail to parse are kept as they are
+    let modded = fastnbt::nbt!({ "id": "mymod:machine", "x": 1, "y": 2, "z": 3, "Energy": 5 });
+    let be = BlockEntity::from_value(modded.clone());
+    assert_eq!(be, BlockEntity::Unknown(modded));
+    assert_eq!(be.position(), Some((1, 2, 3)));
+    assert_eq!(be.id(), Some("mymod:machine".into()));
+
+    let invalid = fastnbt::nbt!({ "id": "minecraft:chest", "x": 1 });
+    assert_eq!(
+        BlockEntity::from_value(invalid.clone()),
+        BlockEntity::Unknown(invalid)
+    );
+
+    // The items of unknown block entities are read from their `Items`
+    let old_chest = BlockEntity::from_value(fastnbt::nbt!({
+        "id": "Chest",
+        "x": 1,
+        "y": 2,
+        "z": 3,
+        "Items": [{ "Slot": 2i8, "id": "minecraft:diamond", "Count": 5i8 }],
+    }));
+    assert!(matches!(old_chest, BlockEntity::Unknown(_)));
+    assert_eq!(old_chest.items().len(), 1);
+    assert_eq!(old_chest.items()[0].id, "diamond".into());
+}
@@ -0,0 +1,104 @@
+//! A small least-recently-used cache, used to keep parsed regions and chunks around between
+//! lookups in a [`Dimension`](crate::Dimension).
+
+use std::{collections::HashMap, hash::Hash};
+
+/// A fixed-capacity map which evicts the least recently used entry when it is full.
+///
+/// The capacities that we use are small (tens of entries), so eviction does a linear scan rather
+/// than maintaining a linked list.
+#[derive(Debug, Clone)]
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (u64, V)>,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Eq + Hash + Copy,
+{
+    /// Create an empty cache which holds at most `capacity` entries
+    ///
+    /// # Panics
+    ///
+    /// - If `capacity` is zero
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Check if `key` is in the cache, without marking it as used
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Get the value for `key`, without marking it as used
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(_, v)| v)
+    }
+
+    /// Get the value for `key`, marking it as the most recently used entry
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    /// Get the value for `key` mutably, marking it as the most recently used entry
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        self.entries.get_mut(key).map(|(t, v)| {
+            *t = tick;
+            v
+        })
+    }
+
+    /// Insert a value into the cache, evicting the least recently used entry if the cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (t, _))| *t)
+                .map(|(k, _)| *k);
+
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(key, (self.tick, value));
+    }
+
+    /// Remove every entry from the cache
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[test]
+fn test_lru_eviction() {
+    let mut cache = LruCache::new(2);
+    cache.insert(1, 'a');
+    cache.insert(2, 'b');
+
+    // Touch `1` so that `2` is the least recently used
+    assert_eq!(cache.get(&1), Some(&'a'));
+    cache.insert(3, 'c');
+
+    assert!(cache.contains(&1));
+    assert!(!cache.contains(&2));
+    assert!(cache.contains(&3));
+
+    // Re-inserting an existing key should not evict anything
+    cache.insert(3, 'd');
+    assert_eq!(cache.get(&1), Some(&'a'));
+    assert_eq!(cache.get(&3), Some(&'d'));
+}
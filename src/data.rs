@@ -1,10 +1,16 @@
 //! Module which holds much of the data related structs that are not nbt
 
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
 use miniz_oxide::inflate;
 
-use crate::{bigendian::BigEndian, nbt, positive_mod, Result};
+use crate::{
+    bigendian::BigEndian,
+    item::{self, FoundItem, ItemStack},
+    legacy,
+    material::{blocks_motion, has_fluid, is_air, is_leaves},
+    nbt, positive_mod, Result,
+};
 
 /// A type of compression used by a chunk
 ///
@@ -56,6 +62,18 @@ impl Deref for ParsedChunk {
     }
 }
 
+impl DerefMut for ParsedChunk {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.nbt
+    }
+}
+
+impl From<nbt::ChunkNbt> for ParsedChunk {
+    fn from(nbt: nbt::ChunkNbt) -> Self {
+        Self { nbt }
+    }
+}
+
 /// Represents one chunk in a region
 #[derive(Debug, Eq, PartialEq)]
 #[repr(C)]
@@ -89,15 +107,19 @@ impl Chunk {
     /// Allocates a new [`Vec`] into which the compressed data will be uncompressed and then parses
     /// the nbt from that [`Vec`]
     pub fn parse(&self) -> Result<ParsedChunk> {
+        ParsedChunk::from_bytes(&self.decompress()?)
+    }
+
+    /// Parse this chunk as a chunk from an entity region file, see [`nbt::EntityChunkNbt`]
+    pub fn parse_entities(&self) -> Result<nbt::EntityChunkNbt> {
+        Ok(fastnbt::from_bytes(&self.decompress()?)?)
+    }
+
+    /// Decompress the data in this chunk into a new [`Vec`]
+    fn decompress(&self) -> Result<Vec<u8>> {
         match self.compression_type {
             CompressionType::GZip => todo!(),
-            CompressionType::Zlib => {
-                let data = &self.compressed_data;
-                let uncompressed = inflate::decompress_to_vec_zlib(data)?;
-                Ok(ParsedChunk {
-                    nbt: fastnbt::from_bytes(&uncompressed)?,
-                })
-            }
+            CompressionType::Zlib => Ok(inflate::decompress_to_vec_zlib(&self.compressed_data)?),
             CompressionType::Uncompressed => todo!(),
             CompressionType::LZ4 => todo!(),
             CompressionType::Custom => todo!(),
@@ -111,43 +133,358 @@ impl Chunk {
 }
 
 impl ParsedChunk {
+    /// Parse a chunk from its uncompressed nbt data
+    ///
+    /// Chunks saved before 21w43a (1.18), which nest their data in a `Level` compound, are
+    /// detected using their `DataVersion` and converted into the modern structure, see
+    /// [`legacy`].
+    pub fn from_bytes(data: &[u8]) -> Result<ParsedChunk> {
+        // Chunks are parsed in the current format first, since legacy chunks (which don't have
+        // the fields at the top level) are rare, so that most chunks are only parsed once
+        let nbt = match fastnbt::from_bytes(data) {
+            Ok(nbt) => nbt,
+            Err(e) => match fastnbt::from_bytes::<legacy::LegacyChunkNbt>(data) {
+                Ok(legacy) => legacy.into(),
+                // Report the error for the format that the chunk claims to be in
+                Err(legacy_e) if legacy::is_legacy(data)? => return Err(legacy_e.into()),
+                Err(_) => return Err(e.into()),
+            },
+        };
+
+        Ok(ParsedChunk { nbt })
+    }
+
     /// Get a chunk section (or subchunk) from the given `block_y` value which is the y value of a _block_ within
     /// the chunk
     pub fn get_chunk_section_at(&self, block_y: i32) -> Option<&nbt::ChunkSection> {
-        let subchunk_y = (block_y / 16) as i8;
+        let subchunk_y = block_y.div_euclid(16) as i8;
 
         self.sections.iter().find(|s| s.y == subchunk_y)
     }
 
+    /// Get a mutable chunk section (or subchunk) from the given `block_y` value which is the y
+    /// value of a _block_ within the chunk
+    pub fn get_chunk_section_at_mut(&mut self, block_y: i32) -> Option<&mut nbt::ChunkSection> {
+        let subchunk_y = block_y.div_euclid(16) as i8;
+
+        self.nbt.sections.iter_mut().find(|s| s.y == subchunk_y)
+    }
+
     /// Get a block from a chunk using block_{x,y,z}.  The x and z coordinates are relative to the chunk,
     /// and the y coordinate is absolute, so (0, 0, 0) is block 0, 0 in the chunk and y=0 in the
     /// world.
     pub fn get_block(&self, block_x: u32, block_y: i32, block_z: u32) -> Option<&nbt::BlockState> {
         let subchunk = self.get_chunk_section_at(block_y)?;
 
-        assert!(block_x < 16);
-        assert!(block_z < 16);
+        subchunk.get_block(block_x, positive_mod!(block_y, 16) as u32, block_z)
+    }
+
+    /// Get an iterator over every block in this chunk, one section at a time in the order that
+    /// the sections are stored.
+    ///
+    /// The iterator yields `(x, y, z, state)` with the same coordinates as
+    /// [`ParsedChunk::get_block`], so `x` and `z` are relative to the chunk and `y` is absolute.
+    /// Each section's packed data is only decoded once, see [`nbt::ChunkSection::blocks`].
+    pub fn blocks(&self) -> impl Iterator<Item = (u32, i32, u32, &nbt::BlockState)> {
+        self.sections.iter().flat_map(|section| {
+            let base_y = section.y as i32 * 16;
+            section
+                .blocks()
+                .map(move |(x, y, z, state)| (x, base_y + y as i32, z, state))
+        })
+    }
 
-        let block_y: u32 = positive_mod!(block_y, 16) as u32;
+    /// Set a block in a chunk using block_{x,y,z}, with the same coordinates as
+    /// [`ParsedChunk::get_block`].
+    ///
+    /// If there is no section at `block_y`, a new section filled with air is created for it.  See
+    /// [`nbt::ChunkSection::set_block`] for how the palette is maintained.
+    pub fn set_block(&mut self, block_x: u32, block_y: i32, block_z: u32, state: nbt::BlockState) {
+        self.get_or_insert_chunk_section_at(block_y).set_block(
+            block_x,
+            positive_mod!(block_y, 16) as u32,
+            block_z,
+            state,
+        );
+    }
 
-        let bs = subchunk.block_states.as_ref()?;
+    /// Get the section which contains `block_y`, creating a new section filled with air if it
+    /// does not exist
+    fn get_or_insert_chunk_section_at(&mut self, block_y: i32) -> &mut nbt::ChunkSection {
+        let subchunk_y = block_y.div_euclid(16) as i8;
 
-        let block_states = if let Some(data) = &bs.data {
-            data
-        } else {
-            // return Some(nbt::BlockState {
-            //     name: "minecraft:air".into(),
-            //     properties: None,
-            // });
-            return None;
+        let sections = &mut self.nbt.sections;
+        let index = match sections.iter().position(|s| s.y == subchunk_y) {
+            Some(i) => i,
+            None => {
+                let i = sections.partition_point(|s| s.y < subchunk_y);
+                sections.insert(i, nbt::ChunkSection::new(subchunk_y));
+                i
+            }
         };
 
-        let bits = std::cmp::max((bs.palette.len() as f32).log2().ceil() as u32, 4);
+        &mut sections[index]
+    }
+
+    /// Get the biome at a position in the chunk using block_{x,y,z}.  The x and z coordinates are
+    /// relative to the chunk, and the y coordinate is absolute, like [`ParsedChunk::get_block`].
+    ///
+    /// Biomes are stored in 4x4x4 cells, so every block within the same cell has the same biome.
+    pub fn get_biome(&self, block_x: u32, block_y: i32, block_z: u32) -> Option<&str> {
+        let subchunk = self.get_chunk_section_at(block_y)?;
+
+        subchunk.get_biome(block_x, positive_mod!(block_y, 16) as u32, block_z)
+    }
+
+    /// Set the biome at a position in the chunk using block_{x,y,z}, with the same coordinates as
+    /// [`ParsedChunk::get_biome`].  This sets the biome of the whole 4x4x4 cell which contains the
+    /// block.
+    ///
+    /// If there is no section at `block_y`, a new section filled with air is created for it.  See
+    /// [`nbt::ChunkSection::set_biome`] for how the palette is maintained.
+    pub fn set_biome(&mut self, block_x: u32, block_y: i32, block_z: u32, biome: &str) {
+        self.get_or_insert_chunk_section_at(block_y).set_biome(
+            block_x,
+            positive_mod!(block_y, 16) as u32,
+            block_z,
+            biome,
+        );
+    }
+
+    /// Get the block light level (`0..=15`) using block_{x,y,z}, with the same coordinates as
+    /// [`ParsedChunk::get_block`].
+    ///
+    /// Returns [`None`] if there is no section at `block_y`.  Sections without a `BlockLight` array
+    /// have a light level of `0`.  The values are only valid if [`nbt::ChunkNbt::is_light_on`] is
+    /// `true`.
+    pub fn get_block_light(&self, block_x: u32, block_y: i32, block_z: u32) -> Option<u8> {
+        let subchunk = self.get_chunk_section_at(block_y)?;
+
+        Some(subchunk.get_block_light(block_x, positive_mod!(block_y, 16) as u32, block_z))
+    }
 
-        let block_index = block_y * 16 * 16 + block_z * 16 + block_x;
-        let block = get_item_in_packed_slice(&block_states, block_index as usize, bits);
+    /// Get the sky light level (`0..=15`) using block_{x,y,z}, with the same coordinates as
+    /// [`ParsedChunk::get_block`].
+    ///
+    /// Returns [`None`] if there is no section at `block_y`.  Sections without a `SkyLight` array
+    /// have a light level of `0`.  The values are only valid if [`nbt::ChunkNbt::is_light_on`] is
+    /// `true`.
+    pub fn get_sky_light(&self, block_x: u32, block_y: i32, block_z: u32) -> Option<u8> {
+        let subchunk = self.get_chunk_section_at(block_y)?;
 
-        Some(&bs.palette[block as usize])
+        Some(subchunk.get_sky_light(block_x, positive_mod!(block_y, 16) as u32, block_z))
+    }
+
+    /// Get the list of pending ticks of `kind` in this chunk
+    pub fn ticks(&self, kind: nbt::TickKind) -> &[nbt::TileTick] {
+        match kind {
+            nbt::TickKind::Block => &self.block_ticks,
+            nbt::TickKind::Fluid => &self.fluid_ticks,
+        }
+    }
+
+    /// Get the mutable list of pending ticks of `kind` in this chunk
+    fn ticks_mut(&mut self, kind: nbt::TickKind) -> &mut Vec<nbt::TileTick> {
+        match kind {
+            nbt::TickKind::Block => &mut self.nbt.block_ticks,
+            nbt::TickKind::Fluid => &mut self.nbt.fluid_ticks,
+        }
+    }
+
+    /// Get the pending ticks of `kind` for blocks in the section with the y value `section_y`
+    /// (i.e. `block_y / 16`)
+    pub fn ticks_in_section(
+        &self,
+        kind: nbt::TickKind,
+        section_y: i8,
+    ) -> impl Iterator<Item = &nbt::TileTick> {
+        self.ticks(kind)
+            .iter()
+            .filter(move |t| t.y.div_euclid(16) == section_y as i32)
+    }
+
+    /// Get the pending ticks of `kind` for the block at block_{x,y,z}, with the same coordinates
+    /// as [`ParsedChunk::get_block`]
+    pub fn ticks_at(
+        &self,
+        kind: nbt::TickKind,
+        block_x: u32,
+        block_y: i32,
+        block_z: u32,
+    ) -> impl Iterator<Item = &nbt::TileTick> {
+        let (x, z) = self.absolute_xz(block_x, block_z);
+        self.ticks(kind)
+            .iter()
+            .filter(move |t| (t.x, t.y, t.z) == (x, block_y, z))
+    }
+
+    /// Schedule a tick of `kind`.  The position of the tick is absolute, like all
+    /// [`nbt::TileTick`]s.
+    ///
+    /// The game only keeps one tick for each block and id, so an existing tick for the same
+    /// position and id is replaced.
+    ///
+    /// # Panics
+    ///
+    /// - If the position of the tick is not inside of this chunk
+    pub fn add_tick(&mut self, kind: nbt::TickKind, tick: nbt::TileTick) {
+        assert_eq!(tick.x.div_euclid(16), self.x_pos);
+        assert_eq!(tick.z.div_euclid(16), self.z_pos);
+
+        let ticks = self.ticks_mut(kind);
+        ticks.retain(|t| (t.x, t.y, t.z, &t.id) != (tick.x, tick.y, tick.z, &tick.id));
+        ticks.push(tick);
+    }
+
+    /// Remove all of the pending ticks of `kind` for the block at block_{x,y,z}, with the same
+    /// coordinates as [`ParsedChunk::get_block`], and return them
+    pub fn remove_ticks_at(
+        &mut self,
+        kind: nbt::TickKind,
+        block_x: u32,
+        block_y: i32,
+        block_z: u32,
+    ) -> Vec<nbt::TileTick> {
+        let pos = self.absolute_xz(block_x, block_z);
+        let ticks = self.ticks_mut(kind);
+
+        let (removed, kept) = std::mem::take(ticks)
+            .into_iter()
+            .partition(|t| (t.x, t.y, t.z) == (pos.0, block_y, pos.1));
+        *ticks = kept;
+
+        removed
+    }
+
+    /// Convert x and z coordinates relative to this chunk into absolute coordinates
+    fn absolute_xz(&self, block_x: u32, block_z: u32) -> (i32, i32) {
+        (
+            self.x_pos * 16 + block_x as i32,
+            self.z_pos * 16 + block_z as i32,
+        )
+    }
+
+    /// Find the blocks in this chunk whose state matches `predicate`, as absolute (x, y, z)
+    /// coordinates in the world.  Sections whose palette has no matching entry are skipped, see
+    /// [`nbt::ChunkSection::find_blocks`].
+    pub fn find_blocks<F>(&self, mut predicate: F) -> Vec<(i32, i32, i32)>
+    where
+        F: FnMut(&nbt::BlockState) -> bool,
+    {
+        let mut found = Vec::new();
+        for section in &self.sections {
+            let base_y = section.y as i32 * 16;
+            found.extend(
+                section
+                    .find_blocks(&mut predicate)
+                    .into_iter()
+                    .map(|(x, y, z)| {
+                        let (x, z) = self.absolute_xz(x, z);
+                        (x, base_y + y as i32, z)
+                    }),
+            );
+        }
+
+        found
+    }
+
+    /// Find every item stack in this chunk which matches `predicate`, including items inside of
+    /// other items (such as shulker boxes and bundles).  Items are searched for in the block
+    /// entities and the entities of this chunk, see [`nbt::ChunkNbt::entities`].
+    pub fn find_items<F>(&self, mut predicate: F) -> Vec<FoundItem>
+    where
+        F: FnMut(&ItemStack) -> bool,
+    {
+        let mut found = Vec::new();
+        for block_entity in &self.block_entities {
+            item::search_block_entity(block_entity, &mut predicate, &mut found);
+        }
+        for entity in &self.entities {
+            item::search_entity(entity, &mut predicate, &mut found);
+        }
+
+        found
+    }
+
+    /// Recompute all six heightmaps from the blocks in this chunk, i.e. after editing blocks with
+    /// [`ParsedChunk::set_block`].
+    ///
+    /// The heightmaps are packed using [`nbt::ChunkNbt::world_height`], so they have the correct
+    /// number of bits for dimensions with custom heights.  Which blocks count for each heightmap
+    /// is decided by a built-in table of vanilla blocks, see [`nbt::HeightMaps`].
+    pub fn recompute_height_maps(&mut self) {
+        self.recompute_height_maps_in(self.world_height());
+    }
+
+    /// Recompute all six heightmaps like [`ParsedChunk::recompute_height_maps`], packed for the
+    /// height of the dimension that this chunk is in, for chunks whose height can't be derived
+    /// from their sections (see [`nbt::ChunkNbt::world_height`]).
+    pub fn recompute_height_maps_in(&mut self, world: nbt::WorldHeight) {
+        /// The heightmaps in the order that they are computed
+        const WORLD_SURFACE: usize = 0;
+        const OCEAN_FLOOR: usize = 1;
+        const MOTION_BLOCKING: usize = 2;
+        const MOTION_BLOCKING_NO_LEAVES: usize = 3;
+
+        let mut heights = [[world.min_y - 1; 256]; 4];
+        let mut found = [[false; 256]; 4];
+        let mut remaining = 4 * 256;
+
+        let mut sections: Vec<_> = self
+            .sections
+            .iter()
+            .filter_map(|s| Some((s.y, s.block_states.as_ref()?)))
+            .collect();
+        sections.sort_by_key(|(y, _)| std::cmp::Reverse(*y));
+
+        for (section_y, block_states) in sections {
+            if remaining == 0 {
+                break;
+            }
+
+            let matches: Vec<[bool; 4]> = block_states
+                .palette
+                .iter()
+                .map(|b| {
+                    let motion = blocks_motion(b) || has_fluid(b);
+                    [
+                        !is_air(b),
+                        blocks_motion(b),
+                        motion,
+                        motion && !is_leaves(b),
+                    ]
+                })
+                .collect();
+
+            let indices = block_states.palette_indices();
+
+            // Go from the top of the section down, so the first match in a column is the highest
+            for i in (0..4096).rev() {
+                let Some(m) = matches.get(indices[i] as usize) else {
+                    continue;
+                };
+
+                let column = i % 256;
+                for map in 0..4 {
+                    if m[map] && !found[map][column] {
+                        found[map][column] = true;
+                        heights[map][column] = section_y as i32 * 16 + (i / 256) as i32;
+                        remaining -= 1;
+                    }
+                }
+            }
+        }
+
+        let hm = &mut self.nbt.height_maps;
+        hm.world_surface = Some(nbt::HeightMap::new(&heights[WORLD_SURFACE], world));
+        hm.world_surface_wg = Some(nbt::HeightMap::new(&heights[WORLD_SURFACE], world));
+        hm.ocean_floor = Some(nbt::HeightMap::new(&heights[OCEAN_FLOOR], world));
+        hm.ocean_floor_wg = Some(nbt::HeightMap::new(&heights[OCEAN_FLOOR], world));
+        hm.motion_blocking = Some(nbt::HeightMap::new(&heights[MOTION_BLOCKING], world));
+        hm.motion_blocking_no_leaves = Some(nbt::HeightMap::new(
+            &heights[MOTION_BLOCKING_NO_LEAVES],
+            world,
+        ));
     }
 
     /// Get a block from a chunk using block_{x,y,z}.  The coordinates are absolute in the
@@ -164,22 +501,3 @@ impl ParsedChunk {
         self.get_block(block_x % 16, block_y, block_z % 16)
     }
 }
-
-fn get_item_in_packed_slice(slice: &[i64], index: usize, bits: u32) -> u64 {
-    let nums_per_u64 = u64::BITS / bits;
-    assert_eq!(
-        (slice.len() as u32),
-        ((4096. / nums_per_u64 as f32).ceil() as u32)
-    );
-    let index_in_num = index as u32 % nums_per_u64;
-    let shifted_num = slice[index / nums_per_u64 as usize] as u64 >> bits * index_in_num;
-    shifted_num & (2u64.pow(bits) - 1)
-}
-
-#[test]
-fn test_get_item_in_packed_slice() {
-    let slice = &[0; 128];
-    assert_eq!(get_item_in_packed_slice(slice, 15, 2), 0);
-    let slice = &[0; 456];
-    assert_eq!(get_item_in_packed_slice(slice, 15, 7), 0);
-}
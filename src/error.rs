@@ -15,6 +15,9 @@ pub enum Error {
     MissingHeader,
     /// An error that may occur when more data is expected by a parser than is provided
     UnexpectedEof,
+    /// An error that may occur when parsing a block state from a string, such as
+    /// `minecraft:oak_stairs[facing=north]`, which contains the string that failed to parse
+    InvalidBlockState(String),
     /// A custom error type that is not used within this crate, but may be needed for implementors
     /// of the traits within this crate.
     Custom(String),
@@ -0,0 +1,432 @@
+//! A typed representation of the item stacks that are stored in containers.
+//!
+//! The format of item stacks changed in 1.20.5 (24w09a): the `tag` compound was replaced by
+//! `components`, and `Count` was renamed to `count`.  [`ItemStack`] reads both forms, and its
+//! accessors look in whichever one the item was saved with.
+//!
+//! - See <https://minecraft.wiki/w/Item_format>
+//! - See <https://minecraft.wiki/w/Data_component_format>
+
+use std::collections::HashMap;
+
+use fastnbt::Value;
+use serde::Deserialize;
+
+use crate::{block_entity::BlockEntity, nbt::NamespacedKey};
+
+/// A stack of items, such as one slot of a chest
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    /// The id of the item (i.e. `minecraft:diamond_sword`)
+    pub id: NamespacedKey,
+    /// The number of items in this stack
+    #[serde(
+        rename = "count",
+        alias = "Count",
+        default = "one",
+        deserialize_with = "deserialize_count"
+    )]
+    pub count: i32,
+    /// The slot that this stack is in, for items which are stored in a container
+    #[serde(rename = "Slot")]
+    pub slot: Option<i8>,
+    /// The extra data of this item, such as its name and enchantments (before 1.20.5)
+    pub tag: Option<Value>,
+    /// The components of this item, keyed by their id (since 1.20.5)
+    #[serde(default)]
+    pub components: HashMap<String, Value>,
+}
+
+/// An enchantment on an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enchantment {
+    /// The id of the enchantment (i.e. `minecraft:sharpness`)
+    pub id: NamespacedKey,
+    /// The level of the enchantment
+    pub level: i32,
+}
+
+/// The default count of an item stack, which is omitted when it is 1 since 1.20.5
+fn one() -> i32 {
+    1
+}
+
+/// Deserialize the count of an item stack, which is a byte before 1.20.5 and an int since
+fn deserialize_count<'de, D>(deserializer: D) -> std::result::Result<i32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    as_int(&Value::deserialize(deserializer)?)
+        .ok_or_else(|| serde::de::Error::custom("expected an integer count"))
+}
+
+/// Deserialize a list of item stacks, skipping any which are not valid (like
+/// [`ItemStack::contents`]), so that one malformed item doesn't make the whole list fail
+pub(crate) fn deserialize_items<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<ItemStack>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let items = Vec::<Value>::deserialize(deserializer)?;
+    Ok(items.iter().filter_map(ItemStack::from_value).collect())
+}
+
+/// Deserialize a single item stack, which is [`None`] if it is not valid
+pub(crate) fn deserialize_item<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<ItemStack>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(ItemStack::from_value(&Value::deserialize(deserializer)?))
+}
+
+impl ItemStack {
+    /// Parse an item stack from its nbt.  Returns [`None`] if it is not a valid item stack.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        fastnbt::from_value(value).ok()
+    }
+
+    /// Get a component of this item, `name` may leave out the `minecraft` namespace (i.e.
+    /// `custom_name`).  Always returns [`None`] for items from before 1.20.5.
+    pub fn component(&self, name: &str) -> Option<&Value> {
+        if name.contains(':') {
+            self.components.get(name)
+        } else {
+            self.components.get(&format!("minecraft:{}", name))
+        }
+    }
+
+    /// Get the custom name of this item as a text component, which is a JSON string before
+    /// 1.21.5.  Returns [`None`] if the item has not been renamed.
+    pub fn custom_name(&self) -> Option<&Value> {
+        self.component("custom_name")
+            .or_else(|| get_path(self.tag.as_ref()?, &["display", "Name"]))
+    }
+
+    /// Get the enchantments on this item, not including those stored in an enchanted book (see
+    /// [`ItemStack::stored_enchantments`])
+    pub fn enchantments(&self) -> Vec<Enchantment> {
+        self.read_enchantments("enchantments", "Enchantments")
+    }
+
+    /// Get the enchantments stored in this item, which is an enchanted book
+    pub fn stored_enchantments(&self) -> Vec<Enchantment> {
+        self.read_enchantments("stored_enchantments", "StoredEnchantments")
+    }
+
+    /// Get the level of the enchantment `id` on this item, including those stored in an
+    /// enchanted book.  Returns [`None`] if the item doesn't have the enchantment.
+    pub fn enchantment_level(&self, id: &NamespacedKey) -> Option<i32> {
+        self.enchantments()
+            .into_iter()
+            .chain(self.stored_enchantments())
+            .find(|e| e.id == *id)
+            .map(|e| e.level)
+    }
+
+    /// Read the enchantments from the `component` component, or the `tag` list `tag`
+    fn read_enchantments(&self, component: &str, tag: &str) -> Vec<Enchantment> {
+        if let Some(Value::Compound(c)) = self.component(component) {
+            // The levels were moved out of the `levels` compound in 1.21.5
+            let levels = match c.get("levels") {
+                Some(Value::Compound(levels)) => levels,
+                _ => c,
+            };
+
+            return levels
+                .iter()
+                .filter_map(|(id, level)| {
+                    Some(Enchantment {
+                        id: id.as_str().into(),
+                        level: as_int(level)?,
+                    })
+                })
+                .collect();
+        }
+
+        let Some(Value::List(list)) = self.tag.as_ref().and_then(|t| get_path(t, &[tag])) else {
+            return Vec::new();
+        };
+
+        list.iter()
+            .filter_map(|e| {
+                let Value::String(id) = get_path(e, &["id"])? else {
+                    return None;
+                };
+
+                Some(Enchantment {
+                    id: id.as_str().into(),
+                    level: as_int(get_path(e, &["lvl"])?)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the items that are directly inside of this item, such as the items in a shulker box or
+    /// a bundle.  Items which could not be parsed are skipped.
+    ///
+    /// See [`ItemStack::contents_recursive`] to also get the items inside of those items.
+    pub fn contents(&self) -> Vec<ItemStack> {
+        // Shulker boxes, with the slot in a wrapper compound
+        if let Some(Value::List(list)) = self.component("container") {
+            return list
+                .iter()
+                .filter_map(|entry| {
+                    let mut item = ItemStack::from_value(get_path(entry, &["item"])?)?;
+                    item.slot = get_path(entry, &["slot"]).and_then(as_int).map(|s| s as i8);
+                    Some(item)
+                })
+                .collect();
+        }
+
+        let list = self.component("bundle_contents").or_else(|| {
+            let tag = self.tag.as_ref()?;
+            // Shulker boxes store their items as a block entity, bundles store them directly
+            get_path(tag, &["BlockEntityTag", "Items"]).or_else(|| get_path(tag, &["Items"]))
+        });
+
+        match list {
+            Some(Value::List(list)) => list.iter().filter_map(ItemStack::from_value).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get every item inside of this item, including those which are nested inside of other
+    /// items (i.e. a bundle inside of a shulker box).  Items are returned depth-first, so each
+    /// item is followed by its own contents.
+    pub fn contents_recursive(&self) -> Vec<ItemStack> {
+        let mut items = Vec::new();
+        for item in self.contents() {
+            let nested = item.contents_recursive();
+            items.push(item);
+            items.extend(nested);
+        }
+
+        items
+    }
+}
+
+/// An item stack which was found by a search, such as [`crate::Dimension::find_items`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundItem {
+    /// The item stack that was found
+    pub item: ItemStack,
+    /// The absolute position of the block entity or entity that holds the item.  Positions of
+    /// entities are rounded down to the block that they are in.
+    pub position: (i32, i32, i32),
+    /// The containers that the item is in, from the outside in: the block entity or entity that
+    /// holds it, followed by each item that it is nested inside of (i.e. a shulker box in a
+    /// chest)
+    pub path: Vec<ItemHolder>,
+}
+
+/// Something which holds items, see [`FoundItem::path`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemHolder {
+    /// The id of the block entity, entity or item (i.e. `minecraft:chest`)
+    pub id: NamespacedKey,
+    /// The slot that this holder is in, for items.  This is [`None`] for block entities and
+    /// entities.
+    pub slot: Option<i8>,
+}
+
+/// Search the items in `block_entity` (and the items inside of those items) for items which match
+/// `predicate`, adding them to `found`
+pub(crate) fn search_block_entity<F>(
+    block_entity: &BlockEntity,
+    predicate: &mut F,
+    found: &mut Vec<FoundItem>,
+) where
+    F: FnMut(&ItemStack) -> bool,
+{
+    let (Some(id), Some(position)) = (block_entity.id(), block_entity.position()) else {
+        return;
+    };
+
+    let mut path = vec![ItemHolder { id, slot: None }];
+    search_items(&block_entity.items(), position, &mut path, predicate, found);
+}
+
+/// Search the items held by `entity` (the `Item` of dropped items and item frames, and the
+/// `Items` of minecarts and boats with chests) for items which match `predicate`, adding them to
+/// `found`
+pub(crate) fn search_entity<F>(entity: &Value, predicate: &mut F, found: &mut Vec<FoundItem>)
+where
+    F: FnMut(&ItemStack) -> bool,
+{
+    let (Some(Value::String(id)), Some(Value::List(pos))) =
+        (get_path(entity, &["id"]), get_path(entity, &["Pos"]))
+    else {
+        return;
+    };
+    let [Value::Double(x), Value::Double(y), Value::Double(z)] = pos[..] else {
+        return;
+    };
+    let position = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+
+    let mut items: Vec<_> = get_path(entity, &["Item"])
+        .and_then(ItemStack::from_value)
+        .into_iter()
+        .collect();
+    if let Some(Value::List(list)) = get_path(entity, &["Items"]) {
+        items.extend(list.iter().filter_map(ItemStack::from_value));
+    }
+
+    let mut path = vec![ItemHolder {
+        id: id.as_str().into(),
+        slot: None,
+    }];
+    search_items(&items, position, &mut path, predicate, found);
+}
+
+/// Search `items` and their contents for items which match `predicate`, where `path` is the
+/// path to the container which holds `items`
+fn search_items<F>(
+    items: &[ItemStack],
+    position: (i32, i32, i32),
+    path: &mut Vec<ItemHolder>,
+    predicate: &mut F,
+    found: &mut Vec<FoundItem>,
+) where
+    F: FnMut(&ItemStack) -> bool,
+{
+    for item in items {
+        if predicate(item) {
+            found.push(FoundItem {
+                item: item.clone(),
+                position,
+                path: path.clone(),
+            });
+        }
+
+        let contents = item.contents();
+        if !contents.is_empty() {
+            path.push(ItemHolder {
+                id: item.id.clone(),
+                slot: item.slot,
+            });
+            search_items(&contents, position, path, predicate, found);
+            path.pop();
+        }
+    }
+}
+
+/// Follow `path` through nested compounds
+fn get_path<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| match value {
+        Value::Compound(c) => c.get(*key),
+        _ => None,
+    })
+}
+
+/// Get the value of an integer tag of any size
+fn as_int(value: &Value) -> Option<i32> {
+    match value {
+        Value::Byte(n) => Some(*n as i32),
+        Value::Short(n) => Some(*n as i32),
+        Value::Int(n) => Some(*n),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_item_stack() {
+    let sword = ItemStack::from_value(&fastnbt::nbt!({
+        "Slot": 3i8,
+        "id": "minecraft:diamond_sword",
+        "Count": 1i8,
+        "tag": {
+            "display": { "Name": r#"{"text":"Excalibur"}"# },
+            "Enchantments": [
+                { "id": "minecraft:sharpness", "lvl": 5i16 },
+                { "id": "minecraft:mending", "lvl": 1i16 },
+            ],
+        },
+    }))
+    .unwrap();
+    assert_eq!(sword.slot, Some(3));
+    assert_eq!(sword.count, 1);
+    assert_eq!(
+        sword.custom_name(),
+        Some(&Value::String(r#"{"text":"Excalibur"}"#.into()))
+    );
+    assert_eq!(sword.enchantment_level(&"sharpness".into()), Some(5));
+    assert_eq!(sword.enchantments().len(), 2);
+    assert_eq!(sword.enchantment_level(&"unbreaking".into()), None);
+
+    let book = ItemStack::from_value(&fastnbt::nbt!({
+        "id": "minecraft:enchanted_book",
+        "count": 2,
+        "components": {
+            "minecraft:stored_enchantments": { "levels": { "minecraft:protection": 4 } },
+        },
+    }))
+    .unwrap();
+    assert_eq!(book.count, 2);
+    assert!(book.enchantments().is_empty());
+    assert_eq!(book.enchantment_level(&"protection".into()), Some(4));
+
+    // Since 1.21.5, enchantments are stored without the `levels` compound
+    let pick = ItemStack::from_value(&fastnbt::nbt!({
+        "id": "minecraft:netherite_pickaxe",
+        "components": {
+            "minecraft:enchantments": { "minecraft:efficiency": 5 },
+            "minecraft:custom_name": "Pick",
+        },
+    }))
+    .unwrap();
+    assert_eq!(pick.count, 1);
+    assert_eq!(pick.enchantment_level(&"efficiency".into()), Some(5));
+    assert_eq!(pick.custom_name(), Some(&Value::String("Pick".into())));
+
+    // A bundle inside of a shulker box, in both formats
+    let legacy = ItemStack::from_value(&fastnbt::nbt!({
+        "id": "minecraft:red_shulker_box",
+        "Count": 1i8,
+        "tag": { "BlockEntityTag": { "Items": [
+            { "Slot": 0i8, "id": "minecraft:elytra", "Count": 1i8 },
+            { "Slot": 1i8, "id": "minecraft:bundle", "Count": 1i8, "tag": { "Items": [
+                { "id": "minecraft:diamond", "Count": 64i8 },
+            ] } },
+        ] } },
+    }))
+    .unwrap();
+    let modern = ItemStack::from_value(&fastnbt::nbt!({
+        "id": "minecraft:red_shulker_box",
+        "count": 1,
+        "components": { "minecraft:container": [
+            { "slot": 0, "item": { "id": "minecraft:elytra", "count": 1 } },
+            { "slot": 1, "item": { "id": "minecraft:bundle", "count": 1, "components": {
+                "minecraft:bundle_contents": [{ "id": "minecraft:diamond", "count": 64 }],
+            } } },
+        ] },
+    }))
+    .unwrap();
+
+    for shulker in [legacy, modern] {
+        let contents = shulker.contents();
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[1].slot, Some(1));
+
+        let ids: Vec<_> = shulker
+            .contents_recursive()
+            .into_iter()
+            .map(|i| (i.id.key, i.count))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("elytra".to_string(), 1),
+                ("bundle".to_string(), 1),
+                ("diamond".to_string(), 64)
+            ]
+        );
+    }
+
+    assert_eq!(
+        ItemStack::from_value(&fastnbt::nbt!({ "Count": 1i8 })),
+        None
+    );
+}
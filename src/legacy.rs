@@ -0,0 +1,469 @@
+//! This module contains the nbt structure of chunks saved before 21w43a (1.18), and how they are
+//! converted into [`ChunkNbt`] so that they can be used through the same accessors as modern
+//! chunks.
+//!
+//! Before 21w43a, all of the chunk's data was nested in a `Level` compound, sections stored their
+//! blocks in `Palette` and `BlockStates`, and biomes were stored for the whole chunk in a single
+//! array of numeric ids.
+//!
+//! [`Chunk::parse`](crate::Chunk::parse) uses the `DataVersion` of a chunk to decide which format
+//! it is in, so there is usually no need to use this module directly.
+//!
+//! Sections saved before 17w47a (1.13) store numeric block ids in `Blocks`, `Data` and `Add`
+//! instead of a palette, these are converted into a palette using [`blocks::block_state`].
+//!
+//! Chunks from MCRegion (`.mcr`) files, used before 1.2, are 128 blocks tall and don't have
+//! sections at all.  Their blocks are split into sections before they are converted.
+//!
+//! - See <https://minecraft.wiki/w/Chunk_format/History>
+
+pub mod blocks;
+mod mcregion;
+
+use fastnbt::{ByteArray, LongArray, Value};
+use serde::Deserialize;
+
+use crate::{
+    block_entity::BlockEntity,
+    nbt::{
+        get_nibble, Biomes, BlockState, BlockStates, ChunkNbt, ChunkSection, HeightMaps,
+        NamespacedKey, TileTick, WorldHeight,
+    },
+    packed::{is_spanning, pack, palette_bits, unpack_spanning},
+    structure::Structures,
+};
+
+/// The `DataVersion` of 21w43a, the first version which doesn't nest the chunk data in a `Level`
+/// compound
+pub const LEVEL_REMOVED_DATA_VERSION: i32 = 2844;
+
+/// Used to read the `DataVersion` of a chunk before deciding how to parse the rest of it
+#[derive(Deserialize)]
+struct VersionProbe {
+    #[serde(rename = "DataVersion")]
+    data_version: Option<i32>,
+}
+
+/// Check if the uncompressed nbt of a chunk is in the format used before 21w43a, which nests the
+/// chunk data in a `Level` compound.  Chunks without a `DataVersion` (saved before 15w32a) are
+/// always legacy.
+pub fn is_legacy(data: &[u8]) -> fastnbt::error::Result<bool> {
+    let probe: VersionProbe = fastnbt::from_bytes(data)?;
+
+    Ok(!matches!(probe.data_version, Some(v) if v >= LEVEL_REMOVED_DATA_VERSION))
+}
+
+/// The number of sections in a chunk before 21w43a, which was always 256 blocks tall
+const SECTIONS: i8 = 16;
+
+/// The nbt structure of a chunk saved before 21w43a
+///
+/// - See <https://minecraft.wiki/w/Chunk_format/History>
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LegacyChunkNbt {
+    /// Version of the chunk NBT structure.  Chunks saved before 15w32a do not have this.
+    #[serde(rename = "DataVersion")]
+    pub data_version: Option<i32>,
+    /// All of the data in the chunk
+    #[serde(rename = "Level")]
+    pub level: Level,
+}
+
+/// The `Level` compound which holds all of the data in a chunk saved before 21w43a
+///
+/// - See <https://minecraft.wiki/w/Chunk_format/History>
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Level {
+    /// `x` position of the chunk (in absolute chunks from world `x`, `z` origin, __not__ relative to the region).
+    #[serde(rename = "xPos")]
+    pub x_pos: i32,
+    /// `z` position of the chunk (in absolute chunks from world `x`, `z` origin, __not__ relative to the region).
+    #[serde(rename = "zPos")]
+    pub z_pos: i32,
+    /// Tick when the chunk was last saved.
+    #[serde(rename = "LastUpdate", default)]
+    pub last_update: i64,
+    /// The cumulative number of ticks players have been in this chunk.
+    #[serde(rename = "InhabitedTime", default)]
+    pub inhabited_time: i64,
+    /// Defines the world generation status of this chunk.  Added in 18w06a.
+    #[serde(rename = "Status")]
+    pub status: Option<NamespacedKey>,
+    /// Whether the light data in this chunk has been calculated.  Added in 19w11a.
+    #[serde(rename = "isLightOn")]
+    pub is_light_on: Option<bool>,
+    /// The numeric ids of the biomes in this chunk.
+    ///
+    /// - Since 19w36a, this is an int array of 1024 4x4x4 cells
+    /// - Since 18w06a, this is an int array of 256 columns
+    /// - Before that, this is a byte array of 256 columns
+    #[serde(rename = "Biomes")]
+    pub biomes: Option<Value>,
+    /// Heightmaps of this chunk.  Added in 18w06a.
+    #[serde(rename = "Heightmaps")]
+    pub height_maps: Option<HeightMaps>,
+    /// The numeric ids of all 32768 blocks in this chunk.  Only present in MCRegion chunks,
+    /// which don't have sections.
+    #[serde(rename = "Blocks")]
+    pub blocks: Option<ByteArray>,
+    /// 16384 bytes recording the data value of each block, 4 bits per block.  Only present in
+    /// MCRegion chunks.
+    #[serde(rename = "Data")]
+    pub data: Option<ByteArray>,
+    /// 16384 bytes recording the amount of block-emitted light in each block, 4 bits per block.
+    /// Only present in MCRegion chunks.
+    #[serde(rename = "BlockLight")]
+    pub block_light: Option<ByteArray>,
+    /// 16384 bytes recording the amount of sunlight or moonlight hitting each block, 4 bits per
+    /// block.  Only present in MCRegion chunks.
+    #[serde(rename = "SkyLight")]
+    pub sky_light: Option<ByteArray>,
+    /// The sections in this chunk, sections which are only air are not saved
+    #[serde(rename = "Sections", default)]
+    pub sections: Vec<LegacySection>,
+    /// List of block entities in this chunk
+    #[serde(rename = "TileEntities", default)]
+    pub block_entities: Vec<BlockEntity>,
+    /// List of entities in this chunk
+    #[serde(rename = "Entities", default)]
+    pub entities: Vec<Value>,
+    /// List of "active" blocks in this chunk waiting to be updated
+    #[serde(rename = "TileTicks", default)]
+    pub block_ticks: Vec<TileTick>,
+    /// List of "active" liquids in this chunk waiting to be updated
+    #[serde(rename = "LiquidTicks", default)]
+    pub fluid_ticks: Vec<TileTick>,
+    /// Lists of the positions of blocks that need to receive an update when a proto-chunk turns
+    /// into a full chunk, one for each section
+    #[serde(rename = "PostProcessing", default)]
+    pub post_processing: Vec<Vec<Value>>,
+    /// Structure data in this chunk
+    #[serde(rename = "Structures")]
+    pub structures: Option<Structures>,
+}
+
+/// A section of a chunk saved before 21w43a
+///
+/// - See <https://minecraft.wiki/w/Chunk_format/History>
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LegacySection {
+    /// y-value of the section
+    #[serde(rename = "Y")]
+    pub y: i8,
+    /// Set of different block states used in this particular section.
+    #[serde(rename = "Palette")]
+    pub palette: Option<Vec<BlockState>>,
+    /// A packed array of 4096 indices pointing to the palette, with a minimum of 4 bits per
+    /// index.
+    #[serde(rename = "BlockStates")]
+    pub block_states: Option<LongArray>,
+    /// The lowest 8 bits of the numeric id of each of the 4096 blocks in this section.  Only
+    /// present before 17w47a.
+    #[serde(rename = "Blocks")]
+    pub blocks: Option<ByteArray>,
+    /// 2048 bytes recording the data value of each block, 4 bits per block.  Only present before
+    /// 17w47a.
+    #[serde(rename = "Data")]
+    pub data: Option<ByteArray>,
+    /// 2048 bytes recording the highest 4 bits of the numeric id of each block, only present if
+    /// any of the ids are above 255.  Only present before 17w47a.
+    #[serde(rename = "Add")]
+    pub add: Option<ByteArray>,
+    /// 2048 bytes recording the amount of block-emitted light in each block, 4 bits per block.
+    #[serde(rename = "BlockLight")]
+    pub block_light: Option<ByteArray>,
+    /// 2048 bytes recording the amount of sunlight or moonlight hitting each block, 4 bits per
+    /// block.
+    #[serde(rename = "SkyLight")]
+    pub sky_light: Option<ByteArray>,
+}
+
+impl LegacySection {
+    /// Convert the blocks in this section into the modern [`BlockStates`], from either the
+    /// palette or the numeric ids
+    fn to_block_states(&self, data_version: i32) -> Option<BlockStates> {
+        if let Some(blocks) = &self.blocks {
+            return Some(self.numeric_block_states(blocks));
+        }
+
+        let palette = self.palette.clone()?;
+        // Sections with one block state in the palette still have data before 21w43a
+        let data = match &self.block_states {
+            Some(_) if palette.len() == 1 => None,
+            Some(data) if is_spanning(data_version) => {
+                Some(unspan_block_states(data, palette.len()))
+            }
+            data => data.clone(),
+        };
+
+        Some(BlockStates { palette, data })
+    }
+
+    /// Build a palette from the numeric ids and data values in this section
+    fn numeric_block_states(&self, blocks: &[i8]) -> BlockStates {
+        let nibble = |arr: &Option<ByteArray>, i| arr.as_deref().map_or(0, |a| get_nibble(a, i));
+
+        // The palette index for each (id, data) pair that has been seen, so that each pair only
+        // needs to be looked up once
+        let mut seen: Vec<((u16, u8), u64)> = Vec::new();
+        let mut palette: Vec<BlockState> = Vec::new();
+        let indices: Vec<u64> = (0..BlockStates::LEN)
+            .map(|i| {
+                let id = blocks.get(i).map_or(0, |b| *b as u8 as u16)
+                    | (nibble(&self.add, i) as u16) << 8;
+                let key = (id, nibble(&self.data, i));
+
+                if let Some((_, index)) = seen.iter().find(|(k, _)| *k == key) {
+                    return *index;
+                }
+
+                // Different data values can map to the same block state
+                let state = blocks::block_state(key.0, key.1);
+                let index = match palette.iter().position(|s| *s == state) {
+                    Some(index) => index,
+                    None => {
+                        palette.push(state);
+                        palette.len() - 1
+                    }
+                } as u64;
+                seen.push((key, index));
+                index
+            })
+            .collect();
+
+        let data = (palette.len() > 1).then(|| {
+            LongArray::new(pack(
+                &indices,
+                palette_bits(palette.len(), BlockStates::MIN_BITS),
+            ))
+        });
+
+        BlockStates { palette, data }
+    }
+}
+
+impl From<LegacyChunkNbt> for ChunkNbt {
+    fn from(value: LegacyChunkNbt) -> Self {
+        let data_version = value.data_version.unwrap_or(0);
+        let mut level = value.level;
+        if let Some(sections) = mcregion::split_sections(&level) {
+            level.sections = sections;
+        }
+
+        let biome_cells = level.biomes.as_ref().map(biome_cells);
+
+        let mut sections: Vec<ChunkSection> = level
+            .sections
+            .into_iter()
+            .map(|s| {
+                let block_states = s.to_block_states(data_version);
+
+                ChunkSection {
+                    block_states,
+                    y: s.y,
+                    biomes: None,
+                    block_light: s.block_light,
+                    sky_light: s.sky_light,
+                }
+            })
+            .collect();
+
+        // Sections which are entirely air are not saved, but every section in the height of the
+        // world is present in modern chunks
+        for y in 0..SECTIONS {
+            match sections.iter_mut().find(|s| s.y == y) {
+                Some(s) if s.block_states.is_none() => {
+                    s.block_states = ChunkSection::new(y).block_states;
+                }
+                Some(_) => {}
+                None => sections.push(ChunkSection::new(y)),
+            }
+        }
+        sections.sort_by_key(|s| s.y);
+
+        if let Some(cells) = &biome_cells {
+            for section in sections.iter_mut().filter(|s| (0..SECTIONS).contains(&s.y)) {
+                let start = section.y as usize * 64;
+                section.biomes = Some(section_biomes(&cells[start..start + 64]));
+            }
+        }
+
+        let mut height_maps = level.height_maps.unwrap_or_default();
+        if is_spanning(data_version) {
+            for height_map in height_maps.iter_mut() {
+                height_map.unspan(WorldHeight {
+                    min_y: 0,
+                    height: SECTIONS as u32 * 16,
+                });
+            }
+        }
+
+        ChunkNbt {
+            data_version,
+            x_pos: level.x_pos,
+            z_pos: level.z_pos,
+            y_pos: 0,
+            status: level.status.unwrap_or_else(|| "full".into()),
+            last_update: level.last_update,
+            block_entities: level.block_entities,
+            entities: level.entities,
+            height_maps,
+            fluid_ticks: level.fluid_ticks,
+            block_ticks: level.block_ticks,
+            inhabited_time: level.inhabited_time,
+            is_light_on: level.is_light_on,
+            blending_data: None,
+            post_processing: level.post_processing,
+            structures: level.structures.unwrap_or_default(),
+            sections,
+        }
+    }
+}
+
+/// Re-pack the block states of a section saved before 20w17a, whose indices can span across two
+/// longs, into the layout used since 20w17a
+fn unspan_block_states(data: &[i64], palette_len: usize) -> LongArray {
+    // Before 20w17a, the array is exactly as long as is needed for 4096 indices
+    let bits = (data.len() * 64 / BlockStates::LEN) as u32;
+    let indices = unpack_spanning(data, bits, BlockStates::LEN);
+
+    LongArray::new(pack(
+        &indices,
+        palette_bits(palette_len, BlockStates::MIN_BITS),
+    ))
+}
+
+/// Convert the chunk-level `Biomes` array into the numeric biome id of all 1024 4x4x4 cells in
+/// the chunk, in the same order as modern biome cells (`y * 16 + z * 4 + x`)
+fn biome_cells(biomes: &Value) -> Vec<i32> {
+    let columns: Vec<i32> = match biomes {
+        // Since 19w36a, the biomes are already stored in cells
+        Value::IntArray(arr) if arr.len() == 1024 => return arr.to_vec(),
+        Value::IntArray(arr) => arr.to_vec(),
+        Value::ByteArray(arr) => arr.iter().map(|b| *b as u8 as i32).collect(),
+        _ => Vec::new(),
+    };
+
+    // Columns are indexed by `z * 16 + x`, so use the column at the corner of each cell
+    (0..1024)
+        .map(|i| {
+            let (x, z) = ((i % 4) * 4, ((i / 4) % 4) * 4);
+            columns.get(z * 16 + x).copied().unwrap_or(1)
+        })
+        .collect()
+}
+
+/// Create the biomes for a section from the numeric ids of its 64 biome cells
+fn section_biomes(cells: &[i32]) -> Biomes {
+    let mut palette: Vec<String> = Vec::new();
+    let indices: Vec<u64> = cells
+        .iter()
+        .map(|id| {
+            let name = biome_name(*id);
+            match palette.iter().position(|p| p == name) {
+                Some(i) => i as u64,
+                None => {
+                    palette.push(name.into());
+                    palette.len() as u64 - 1
+                }
+            }
+        })
+        .collect();
+
+    let bits = palette_bits(palette.len(), 0);
+    Biomes {
+        data: (bits > 0).then(|| LongArray::new(pack(&indices, bits))),
+        palette,
+    }
+}
+
+/// Get the name of a biome from its numeric id, as of 1.17 (the last version to use numeric ids).
+/// Unknown ids are treated as plains.
+///
+/// - See <https://minecraft.wiki/w/Biome/ID>
+pub fn biome_name(id: i32) -> &'static str {
+    match id {
+        0 => "minecraft:ocean",
+        1 => "minecraft:plains",
+        2 => "minecraft:desert",
+        3 => "minecraft:mountains",
+        4 => "minecraft:forest",
+        5 => "minecraft:taiga",
+        6 => "minecraft:swamp",
+        7 => "minecraft:river",
+        8 => "minecraft:nether_wastes",
+        9 => "minecraft:the_end",
+        10 => "minecraft:frozen_ocean",
+        11 => "minecraft:frozen_river",
+        12 => "minecraft:snowy_tundra",
+        13 => "minecraft:snowy_mountains",
+        14 => "minecraft:mushroom_fields",
+        15 => "minecraft:mushroom_field_shore",
+        16 => "minecraft:beach",
+        17 => "minecraft:desert_hills",
+        18 => "minecraft:wooded_hills",
+        19 => "minecraft:taiga_hills",
+        20 => "minecraft:mountain_edge",
+        21 => "minecraft:jungle",
+        22 => "minecraft:jungle_hills",
+        23 => "minecraft:jungle_edge",
+        24 => "minecraft:deep_ocean",
+        25 => "minecraft:stone_shore",
+        26 => "minecraft:snowy_beach",
+        27 => "minecraft:birch_forest",
+        28 => "minecraft:birch_forest_hills",
+        29 => "minecraft:dark_forest",
+        30 => "minecraft:snowy_taiga",
+        31 => "minecraft:snowy_taiga_hills",
+        32 => "minecraft:giant_tree_taiga",
+        33 => "minecraft:giant_tree_taiga_hills",
+        34 => "minecraft:wooded_mountains",
+        35 => "minecraft:savanna",
+        36 => "minecraft:savanna_plateau",
+        37 => "minecraft:badlands",
+        38 => "minecraft:wooded_badlands_plateau",
+        39 => "minecraft:badlands_plateau",
+        40 => "minecraft:small_end_islands",
+        41 => "minecraft:end_midlands",
+        42 => "minecraft:end_highlands",
+        43 => "minecraft:end_barrens",
+        44 => "minecraft:warm_ocean",
+        45 => "minecraft:lukewarm_ocean",
+        46 => "minecraft:cold_ocean",
+        47 => "minecraft:deep_warm_ocean",
+        48 => "minecraft:deep_lukewarm_ocean",
+        49 => "minecraft:deep_cold_ocean",
+        50 => "minecraft:deep_frozen_ocean",
+        127 => "minecraft:the_void",
+        129 => "minecraft:sunflower_plains",
+        130 => "minecraft:desert_lakes",
+        131 => "minecraft:gravelly_mountains",
+        132 => "minecraft:flower_forest",
+        133 => "minecraft:taiga_mountains",
+        134 => "minecraft:swamp_hills",
+        140 => "minecraft:ice_spikes",
+        149 => "minecraft:modified_jungle",
+        151 => "minecraft:modified_jungle_edge",
+        155 => "minecraft:tall_birch_forest",
+        156 => "minecraft:tall_birch_hills",
+        157 => "minecraft:dark_forest_hills",
+        158 => "minecraft:snowy_taiga_mountains",
+        160 => "minecraft:giant_spruce_taiga",
+        161 => "minecraft:giant_spruce_taiga_hills",
+        162 => "minecraft:modified_gravelly_mountains",
+        163 => "minecraft:shattered_savanna",
+        164 => "minecraft:shattered_savanna_plateau",
+        165 => "minecraft:eroded_badlands",
+        166 => "minecraft:modified_wooded_badlands_plateau",
+        167 => "minecraft:modified_badlands_plateau",
+        168 => "minecraft:bamboo_jungle",
+        169 => "minecraft:bamboo_jungle_hills",
+        170 => "minecraft:soul_sand_valley",
+        171 => "minecraft:crimson_forest",
+        172 => "minecraft:warped_forest",
+        173 => "minecraft:basalt_deltas",
+        174 => "minecraft:dripstone_caves",
+        175 => "minecraft:lush_caves",
+        _ => "minecraft:plains",
+    }
+}
@@ -0,0 +1,487 @@
+//! The mapping from the numeric block ids and data values used before 17w47a (1.13) to block
+//! states.
+//!
+//! Block states are named as they were in 1.13, when numeric ids were removed.  Only the properties
+//! which can be derived from the data value alone and that change what the block is (such as the
+//! level of a fluid, the axis of a log, or the type of a slab) are set, all others are left out so
+//! that the game uses their defaults.
+//!
+//! - See <https://minecraft.wiki/w/Java_Edition_data_values/Pre-flattening>
+
+use crate::nbt::{BlockState, NamespacedKey};
+
+/// The order of the 16 colours used by wool, stained glass, terracotta, etc.
+const COLOURS: [&str; 16] = [
+    "white",
+    "orange",
+    "magenta",
+    "light_blue",
+    "yellow",
+    "lime",
+    "pink",
+    "gray",
+    "light_gray",
+    "cyan",
+    "purple",
+    "blue",
+    "brown",
+    "green",
+    "red",
+    "black",
+];
+
+/// The order of the wood types used by planks, saplings, slabs, etc.
+const WOODS: [&str; 6] = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+/// Get the block state for a numeric block id and data value
+///
+/// `id` includes the extra 4 bits from the `Add` array, and only the lowest 4 bits of `data` are
+/// used.  Ids which are unknown (such as those added by mods) are kept as a placeholder block
+/// named `legacy:<id>`, with the data value in a `data` property, so that they can still be told
+/// apart and mapped by the caller.
+pub fn block_state(id: u16, data: u8) -> BlockState {
+    let data = data & 0xf;
+    // Many blocks use the lowest bits of the data value for their variant, and the highest bit for
+    // whether it's the top half or not
+    let top = data & 8 != 0;
+    // Used for properties which are numbers, such as the level of a fluid or the age of a crop
+    let num = data.to_string();
+
+    match id {
+        0 => state("air", &[]),
+        1 => variant(
+            &[
+                "stone",
+                "granite",
+                "polished_granite",
+                "diorite",
+                "polished_diorite",
+                "andesite",
+                "polished_andesite",
+            ],
+            data,
+        ),
+        2 => state("grass_block", &[]),
+        3 => variant(&["dirt", "coarse_dirt", "podzol"], data),
+        4 => state("cobblestone", &[]),
+        5 => wood("planks", data),
+        6 => wood("sapling", data & 7),
+        7 => state("bedrock", &[]),
+        8 | 9 => state("water", &[("level", &num)]),
+        10 | 11 => state("lava", &[("level", &num)]),
+        12 => variant(&["sand", "red_sand"], data),
+        13 => state("gravel", &[]),
+        14 => state("gold_ore", &[]),
+        15 => state("iron_ore", &[]),
+        16 => state("coal_ore", &[]),
+        17 => log(&WOODS[..4], data),
+        18 => wood("leaves", data & 3),
+        19 => variant(&["sponge", "wet_sponge"], data),
+        20 => state("glass", &[]),
+        21 => state("lapis_ore", &[]),
+        22 => state("lapis_block", &[]),
+        23 => state("dispenser", &[]),
+        24 => variant(&["sandstone", "chiseled_sandstone", "cut_sandstone"], data),
+        25 => state("note_block", &[]),
+        26 => state("red_bed", &[]),
+        27 => state("powered_rail", &[]),
+        28 => state("detector_rail", &[]),
+        29 => state("sticky_piston", &[]),
+        30 => state("cobweb", &[]),
+        31 => variant(&["dead_bush", "grass", "fern"], data),
+        32 => state("dead_bush", &[]),
+        33 => state("piston", &[]),
+        34 => state("piston_head", &[]),
+        35 => colour("wool", data),
+        36 => state("moving_piston", &[]),
+        37 => state("dandelion", &[]),
+        38 => variant(
+            &[
+                "poppy",
+                "blue_orchid",
+                "allium",
+                "azure_bluet",
+                "red_tulip",
+                "orange_tulip",
+                "white_tulip",
+                "pink_tulip",
+                "oxeye_daisy",
+            ],
+            data,
+        ),
+        39 => state("brown_mushroom", &[]),
+        40 => state("red_mushroom", &[]),
+        41 => state("gold_block", &[]),
+        42 => state("iron_block", &[]),
+        43 => stone_slab(data, Some("double")),
+        44 => stone_slab(data, None),
+        45 => state("bricks", &[]),
+        46 => state("tnt", &[]),
+        47 => state("bookshelf", &[]),
+        48 => state("mossy_cobblestone", &[]),
+        49 => state("obsidian", &[]),
+        50 => torch("torch", "wall_torch", data, &[]),
+        51 => state("fire", &[]),
+        52 => state("spawner", &[]),
+        53 => state("oak_stairs", &[]),
+        54 => state("chest", &[]),
+        55 => state("redstone_wire", &[("power", &num)]),
+        56 => state("diamond_ore", &[]),
+        57 => state("diamond_block", &[]),
+        58 => state("crafting_table", &[]),
+        59 => state("wheat", &[("age", &num)]),
+        60 => state("farmland", &[]),
+        61 => state("furnace", &[]),
+        62 => state("furnace", &[("lit", "true")]),
+        63 => state("sign", &[]),
+        64 => door("oak_door", top),
+        65 => state("ladder", &[]),
+        66 => state("rail", &[]),
+        67 => state("cobblestone_stairs", &[]),
+        68 => state("wall_sign", &[]),
+        69 => state("lever", &[]),
+        70 => state("stone_pressure_plate", &[]),
+        71 => door("iron_door", top),
+        72 => state("oak_pressure_plate", &[]),
+        73 => state("redstone_ore", &[]),
+        74 => state("redstone_ore", &[("lit", "true")]),
+        75 => torch(
+            "redstone_torch",
+            "redstone_wall_torch",
+            data,
+            &[("lit", "false")],
+        ),
+        76 => torch("redstone_torch", "redstone_wall_torch", data, &[]),
+        77 => state("stone_button", &[]),
+        78 => state("snow", &[("layers", &((data & 7) + 1).to_string())]),
+        79 => state("ice", &[]),
+        80 => state("snow_block", &[]),
+        81 => state("cactus", &[]),
+        82 => state("clay", &[]),
+        83 => state("sugar_cane", &[]),
+        84 => state("jukebox", &[]),
+        85 => state("oak_fence", &[]),
+        86 => state("carved_pumpkin", &[]),
+        87 => state("netherrack", &[]),
+        88 => state("soul_sand", &[]),
+        89 => state("glowstone", &[]),
+        90 => state("nether_portal", &[]),
+        91 => state("jack_o_lantern", &[]),
+        92 => state("cake", &[]),
+        93 => state("repeater", &[]),
+        94 => state("repeater", &[("powered", "true")]),
+        95 => colour("stained_glass", data),
+        96 => state("oak_trapdoor", &[]),
+        97 => variant(
+            &[
+                "infested_stone",
+                "infested_cobblestone",
+                "infested_stone_bricks",
+                "infested_mossy_stone_bricks",
+                "infested_cracked_stone_bricks",
+                "infested_chiseled_stone_bricks",
+            ],
+            data,
+        ),
+        98 => variant(
+            &[
+                "stone_bricks",
+                "mossy_stone_bricks",
+                "cracked_stone_bricks",
+                "chiseled_stone_bricks",
+            ],
+            data,
+        ),
+        99 => state("brown_mushroom_block", &[]),
+        100 => state("red_mushroom_block", &[]),
+        101 => state("iron_bars", &[]),
+        102 => state("glass_pane", &[]),
+        103 => state("melon", &[]),
+        104 => state("pumpkin_stem", &[("age", &num)]),
+        105 => state("melon_stem", &[("age", &num)]),
+        106 => state("vine", &[]),
+        107 => state("oak_fence_gate", &[]),
+        108 => state("brick_stairs", &[]),
+        109 => state("stone_brick_stairs", &[]),
+        110 => state("mycelium", &[]),
+        111 => state("lily_pad", &[]),
+        112 => state("nether_bricks", &[]),
+        113 => state("nether_brick_fence", &[]),
+        114 => state("nether_brick_stairs", &[]),
+        115 => state("nether_wart", &[("age", &num)]),
+        116 => state("enchanting_table", &[]),
+        117 => state("brewing_stand", &[]),
+        118 => state("cauldron", &[("level", &num)]),
+        119 => state("end_portal", &[]),
+        120 => state("end_portal_frame", &[]),
+        121 => state("end_stone", &[]),
+        122 => state("dragon_egg", &[]),
+        123 => state("redstone_lamp", &[]),
+        124 => state("redstone_lamp", &[("lit", "true")]),
+        125 => slab(
+            WOODS.get(data as usize & 7).unwrap_or(&WOODS[0]),
+            Some("double"),
+        ),
+        126 => slab(WOODS.get(data as usize & 7).unwrap_or(&WOODS[0]), half(top)),
+        127 => state("cocoa", &[]),
+        128 => state("sandstone_stairs", &[]),
+        129 => state("emerald_ore", &[]),
+        130 => state("ender_chest", &[]),
+        131 => state("tripwire_hook", &[]),
+        132 => state("tripwire", &[]),
+        133 => state("emerald_block", &[]),
+        134 => state("spruce_stairs", &[]),
+        135 => state("birch_stairs", &[]),
+        136 => state("jungle_stairs", &[]),
+        137 => state("command_block", &[]),
+        138 => state("beacon", &[]),
+        139 => variant(&["cobblestone_wall", "mossy_cobblestone_wall"], data),
+        140 => state("flower_pot", &[]),
+        141 => state("carrots", &[("age", &num)]),
+        142 => state("potatoes", &[("age", &num)]),
+        143 => state("oak_button", &[]),
+        144 => state("skeleton_skull", &[]),
+        145 => variant(&["anvil", "chipped_anvil", "damaged_anvil"], data >> 2),
+        146 => state("trapped_chest", &[]),
+        147 => state("light_weighted_pressure_plate", &[]),
+        148 => state("heavy_weighted_pressure_plate", &[]),
+        149 => state("comparator", &[]),
+        150 => state("comparator", &[("powered", "true")]),
+        151 => state("daylight_detector", &[]),
+        152 => state("redstone_block", &[]),
+        153 => state("nether_quartz_ore", &[]),
+        154 => state("hopper", &[]),
+        155 => match data {
+            0 => state("quartz_block", &[]),
+            1 => state("chiseled_quartz_block", &[]),
+            3 => state("quartz_pillar", &[("axis", "x")]),
+            4 => state("quartz_pillar", &[("axis", "z")]),
+            _ => state("quartz_pillar", &[("axis", "y")]),
+        },
+        156 => state("quartz_stairs", &[]),
+        157 => state("activator_rail", &[]),
+        158 => state("dropper", &[]),
+        159 => colour("terracotta", data),
+        160 => colour("stained_glass_pane", data),
+        161 => wood("leaves", (data & 1) + 4),
+        162 => log(&WOODS[4..], data),
+        163 => state("acacia_stairs", &[]),
+        164 => state("dark_oak_stairs", &[]),
+        165 => state("slime_block", &[]),
+        166 => state("barrier", &[]),
+        167 => state("iron_trapdoor", &[]),
+        168 => variant(
+            &["prismarine", "prismarine_bricks", "dark_prismarine"],
+            data,
+        ),
+        169 => state("sea_lantern", &[]),
+        170 => state("hay_block", &[("axis", axis(data))]),
+        171 => colour("carpet", data),
+        172 => state("terracotta", &[]),
+        173 => state("coal_block", &[]),
+        174 => state("packed_ice", &[]),
+        175 => {
+            // The top half of a double plant doesn't store its type (it's taken from the bottom
+            // half), so it's treated as a sunflower
+            let plants = [
+                "sunflower",
+                "lilac",
+                "tall_grass",
+                "large_fern",
+                "rose_bush",
+                "peony",
+            ];
+            let name = plants.get(data as usize & 7).filter(|_| !top);
+            let half = if top { "upper" } else { "lower" };
+            state(name.unwrap_or(&plants[0]), &[("half", half)])
+        }
+        176 => state("white_banner", &[]),
+        177 => state("white_wall_banner", &[]),
+        178 => state("daylight_detector", &[("inverted", "true")]),
+        179 => variant(
+            &[
+                "red_sandstone",
+                "chiseled_red_sandstone",
+                "cut_red_sandstone",
+            ],
+            data,
+        ),
+        180 => state("red_sandstone_stairs", &[]),
+        181 => slab("red_sandstone", Some("double")),
+        182 => slab("red_sandstone", half(top)),
+        183 => state("spruce_fence_gate", &[]),
+        184 => state("birch_fence_gate", &[]),
+        185 => state("jungle_fence_gate", &[]),
+        186 => state("dark_oak_fence_gate", &[]),
+        187 => state("acacia_fence_gate", &[]),
+        188 => state("spruce_fence", &[]),
+        189 => state("birch_fence", &[]),
+        190 => state("jungle_fence", &[]),
+        191 => state("dark_oak_fence", &[]),
+        192 => state("acacia_fence", &[]),
+        193 => door("spruce_door", top),
+        194 => door("birch_door", top),
+        195 => door("jungle_door", top),
+        196 => door("acacia_door", top),
+        197 => door("dark_oak_door", top),
+        198 => state("end_rod", &[]),
+        199 => state("chorus_plant", &[]),
+        200 => state("chorus_flower", &[]),
+        201 => state("purpur_block", &[]),
+        202 => state("purpur_pillar", &[("axis", axis(data))]),
+        203 => state("purpur_stairs", &[]),
+        204 => slab("purpur", Some("double")),
+        205 => slab("purpur", half(top)),
+        206 => state("end_stone_bricks", &[]),
+        207 => state("beetroots", &[("age", &num)]),
+        208 => state("grass_path", &[]),
+        209 => state("end_gateway", &[]),
+        210 => state("repeating_command_block", &[]),
+        211 => state("chain_command_block", &[]),
+        212 => state("frosted_ice", &[]),
+        213 => state("magma_block", &[]),
+        214 => state("nether_wart_block", &[]),
+        215 => state("red_nether_bricks", &[]),
+        216 => state("bone_block", &[("axis", axis(data))]),
+        217 => state("structure_void", &[]),
+        218 => state("observer", &[]),
+        219..=234 => colour("shulker_box", (id - 219) as u8),
+        235..=250 => colour("glazed_terracotta", (id - 235) as u8),
+        251 => colour("concrete", data),
+        252 => colour("concrete_powder", data),
+        255 => state("structure_block", &[]),
+        _ => {
+            let mut state = BlockState::new(NamespacedKey::new("legacy", id.to_string()));
+            state.set("data", &num);
+            state
+        }
+    }
+}
+
+/// Create a block state in the `minecraft` namespace
+fn state(name: &str, properties: &[(&str, &str)]) -> BlockState {
+    let mut state = BlockState::new(NamespacedKey::minecraft(name.into()));
+    for (k, v) in properties {
+        state.set(*k, v);
+    }
+
+    state
+}
+
+/// Pick a block from `names` using the data value, using the first if it's out of range
+fn variant(names: &[&str], data: u8) -> BlockState {
+    state(names.get(data as usize).unwrap_or(&names[0]), &[])
+}
+
+/// Create a block which has a variant for each of the 16 colours
+fn colour(suffix: &str, data: u8) -> BlockState {
+    state(&format!("{}_{}", COLOURS[data as usize & 0xf], suffix), &[])
+}
+
+/// Create a block which has a variant for each of the wood types
+fn wood(suffix: &str, data: u8) -> BlockState {
+    let wood = WOODS.get(data as usize).unwrap_or(&WOODS[0]);
+    state(&format!("{}_{}", wood, suffix), &[])
+}
+
+/// Create a log, where the lowest 2 bits of `data` are the index into `woods` and the highest 2
+/// bits are the axis (or a log with bark on all sides)
+fn log(woods: &[&str], data: u8) -> BlockState {
+    let wood = woods.get(data as usize & 3).unwrap_or(&woods[0]);
+    match data >> 2 {
+        1 => state(&format!("{}_log", wood), &[("axis", "x")]),
+        2 => state(&format!("{}_log", wood), &[("axis", "z")]),
+        3 => state(&format!("{}_wood", wood), &[("axis", "y")]),
+        _ => state(&format!("{}_log", wood), &[("axis", "y")]),
+    }
+}
+
+/// Get the axis of a pillar-like block (hay bales, bone blocks, etc.)
+fn axis(data: u8) -> &'static str {
+    match data >> 2 {
+        1 => "x",
+        2 => "z",
+        _ => "y",
+    }
+}
+
+/// Get the type of a single slab from whether it's in the top half of the block
+fn half(top: bool) -> Option<&'static str> {
+    Some(if top { "top" } else { "bottom" })
+}
+
+/// Create a slab of `material` with the given `type` property
+fn slab(material: &str, ty: Option<&str>) -> BlockState {
+    match ty {
+        Some(ty) => state(&format!("{}_slab", material), &[("type", ty)]),
+        None => state(&format!("{}_slab", material), &[]),
+    }
+}
+
+/// Create one of the stone slabs, which share ids 43 (double) and 44 (single)
+fn stone_slab(data: u8, ty: Option<&str>) -> BlockState {
+    let materials = [
+        "stone",
+        "sandstone",
+        "petrified_oak",
+        "cobblestone",
+        "brick",
+        "stone_brick",
+        "nether_brick",
+        "quartz",
+    ];
+
+    slab(
+        materials[data as usize & 7],
+        ty.or_else(|| half(data & 8 != 0)),
+    )
+}
+
+/// Create a door, where the data value only tells us which half it is
+fn door(name: &str, top: bool) -> BlockState {
+    state(name, &[("half", if top { "upper" } else { "lower" })])
+}
+
+/// Create a torch, which is a standing torch with a data value of 5 (or 0) and a wall torch
+/// otherwise
+fn torch(standing: &str, wall: &str, data: u8, properties: &[(&str, &str)]) -> BlockState {
+    let facing = match data {
+        1 => "east",
+        2 => "west",
+        3 => "south",
+        4 => "north",
+        _ => return state(standing, properties),
+    };
+
+    let mut properties = properties.to_vec();
+    properties.push(("facing", facing));
+    state(wall, &properties)
+}
+
+#[test]
+fn test_block_state() {
+    assert_eq!(block_state(0, 0), state("air", &[]));
+    assert_eq!(block_state(1, 3), state("diorite", &[]));
+    assert_eq!(block_state(9, 0), state("water", &[("level", "0")]));
+    assert_eq!(block_state(17, 10), state("birch_log", &[("axis", "z")]));
+    assert_eq!(block_state(162, 1), state("dark_oak_log", &[("axis", "y")]));
+    assert_eq!(block_state(161, 1), state("dark_oak_leaves", &[]));
+    assert_eq!(block_state(35, 14), state("red_wool", &[]));
+    assert_eq!(
+        block_state(44, 13),
+        state("stone_brick_slab", &[("type", "top")])
+    );
+    assert_eq!(
+        block_state(43, 0),
+        state("stone_slab", &[("type", "double")])
+    );
+    assert_eq!(
+        block_state(50, 4),
+        state("wall_torch", &[("facing", "north")])
+    );
+    assert_eq!(block_state(221, 0), state("magenta_shulker_box", &[]));
+    // Unknown ids are kept as placeholders
+    let unknown = block_state(1000, 0x13);
+    assert_eq!(unknown.to_string(), "legacy:1000[data=3]");
+    assert_eq!(unknown.get_int("data"), Some(3));
+}
@@ -0,0 +1,70 @@
+//! Support for chunks saved in the MCRegion format (`.mcr` files), which was used before 1.2.
+//!
+//! MCRegion chunks have the same `Level` compound as later legacy chunks, but are always 128 blocks
+//! tall and store all of their blocks in one set of arrays (`Blocks`, `Data`, `BlockLight` and
+//! `SkyLight`) instead of in sections.  The arrays are ordered by x, then z, then y (so the index
+//! of a block is `x * 2048 + z * 128 + y`).
+//!
+//! - See <https://minecraft.wiki/w/Region_file_format#Legacy_formats>
+
+use fastnbt::ByteArray;
+
+use super::{LegacySection, Level};
+use crate::nbt::get_nibble;
+
+/// The number of blocks in an MCRegion chunk
+const BLOCKS: usize = 16 * 16 * HEIGHT;
+/// The height of an MCRegion chunk
+const HEIGHT: usize = 128;
+
+/// Split the chunk-wide arrays of an MCRegion chunk into sections, as they are stored in anvil
+/// chunks, so that they can be converted in the same way.
+///
+/// Returns [`None`] if `level` is not from an MCRegion chunk.
+pub(super) fn split_sections(level: &Level) -> Option<Vec<LegacySection>> {
+    let blocks = level.blocks.as_deref()?;
+    if !level.sections.is_empty() || blocks.len() != BLOCKS {
+        return None;
+    }
+
+    let sections = (0..(HEIGHT / 16) as i8)
+        .map(|y| {
+            // The index into the chunk-wide arrays for each block in the section, in the order
+            // used by sections
+            let indices = (0..4096).map(|i| {
+                let (x, z, dy) = (i % 16, (i / 16) % 16, i / 256);
+                x * 2048 + z * 128 + y as usize * 16 + dy
+            });
+
+            let section_blocks = indices.clone().map(|i| blocks[i]).collect();
+            let nibbles = |arr: &Option<ByteArray>| {
+                arr.as_deref().map(|arr| {
+                    ByteArray::new(pack_nibbles(indices.clone().map(|i| get_nibble(arr, i))))
+                })
+            };
+
+            LegacySection {
+                y,
+                palette: None,
+                block_states: None,
+                blocks: Some(ByteArray::new(section_blocks)),
+                data: nibbles(&level.data),
+                add: None,
+                block_light: nibbles(&level.block_light),
+                sky_light: nibbles(&level.sky_light),
+            }
+        })
+        .collect();
+
+    Some(sections)
+}
+
+/// Pack 4-bit values into a nibble array, where even indices are stored in the low bits
+fn pack_nibbles(values: impl Iterator<Item = u8>) -> Vec<i8> {
+    let mut arr = vec![0i8; 2048];
+    for (i, value) in values.enumerate() {
+        arr[i / 2] |= ((value & 0xf) << ((i % 2) * 4)) as i8;
+    }
+
+    arr
+}
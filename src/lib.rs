@@ -45,24 +45,36 @@
 //     more (dimension id, world folder, etc)
 
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     io::{self, Read},
     ops::Deref,
     path::{Path, PathBuf},
 };
 
 use bigendian::BigEndian;
+use cache::LruCache;
 use error::Error;
 
 pub use data::*;
 pub use error::Result;
 
 mod bigendian;
+pub mod block_entity;
+mod cache;
 pub mod data;
 pub mod error;
+pub mod item;
+pub mod legacy;
+pub mod light;
+mod material;
 pub mod nbt;
+mod packed;
+pub mod stats;
+pub mod structure;
 #[macro_use]
 mod util;
+pub mod upgrade;
+pub mod version;
 
 #[cfg(test)]
 mod test;
@@ -155,6 +167,21 @@ impl Region {
         }
     }
 
+    /// Allocate this [`Region`] into a new [`Box`] which is owned by the caller
+    pub fn boxed(&self) -> Box<Self> {
+        let len = std::mem::size_of_val(self);
+        // SAFETY: `Region` is made up entirely of bytes, so we can view all of it as a slice
+        let bytes = unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, len) };
+        let b = Box::into_raw(bytes.to_vec().into_boxed_slice());
+        // SAFETY: The box holds exactly `size_of_val(self)` bytes, so we can shrink the fat
+        // pointer by the 8192 byte header and turn it back into a `Box<Region>`
+        unsafe {
+            Box::from_raw(
+                std::ptr::slice_from_raw_parts_mut(b as *mut u8, len - 8192) as *mut Region
+            )
+        }
+    }
+
     /// Convert x and z into the correct index into the `locations` and `timestamps` arrays
     ///
     /// # Panics
@@ -296,6 +323,9 @@ impl RegionFile {
 }
 
 /// Create an iterator over the contents of a directory, allowing each region within to be parsed
+///
+/// Worlds from before 1.2 use MCRegion (`.mcr`) files, which are kept when the world is converted
+/// to anvil (`.mca`) files.  If both exist for the same region, only the `.mca` file is included.
 pub fn parse_directory<P>(path: P) -> io::Result<impl Iterator<Item = RegionFile>>
 where
     P: AsRef<Path>,
@@ -313,6 +343,10 @@ where
             return None;
         }
 
+        if path.extension().is_some_and(|e| e == "mcr") && path.with_extension("mca").is_file() {
+            return None;
+        }
+
         Some(RegionFile::new(path))
     });
 
@@ -383,6 +417,16 @@ impl From<Box<Region>> for RegionRef<'_> {
     }
 }
 
+impl RegionRef<'_> {
+    /// Convert this into an owned [`Region`], copying the data if it is borrowed
+    pub fn into_owned(self) -> Box<Region> {
+        match self {
+            RegionRef::Borrowed(r) => r.boxed(),
+            RegionRef::Owned(r) => r,
+        }
+    }
+}
+
 impl Deref for RegionRef<'_> {
     type Target = Region;
 
@@ -406,6 +450,12 @@ pub trait RegionParser {
     /// be parsed by this [`RegionParser`] if there is no information as to which region this is,
     /// then [`None`] should be returned.
     fn position(&self) -> Option<(i32, i32)>;
+
+    /// Check if this is an MCRegion (`.mcr`) region from before 1.2.  When a [`Dimension`] has
+    /// both an MCRegion and an anvil region at the same position, the anvil region is used.
+    fn is_mcregion(&self) -> bool {
+        false
+    }
 }
 
 impl RegionParser for RegionFile {
@@ -424,24 +474,35 @@ impl RegionParser for RegionFile {
             return None;
         };
 
-        if parts.next() != Some("mca") {
+        if !matches!(parts.next(), Some("mca" | "mcr")) {
             return None;
         }
 
         Some((x, z))
     }
 
+    fn is_mcregion(&self) -> bool {
+        self.path.extension().is_some_and(|e| e == "mcr")
+    }
+
     fn parse(&self) -> Result<RegionRef<'_>> {
         let mut file = std::fs::File::open(&self.path)?;
         Ok(Region::from_reader(&mut file)?.into())
     }
 }
 
+/// The number of parsed regions that a [`Dimension`] keeps cached for block lookups
+const REGION_CACHE_SIZE: usize = 4;
+/// The number of parsed chunks that a [`Dimension`] keeps cached for block lookups
+const CHUNK_CACHE_SIZE: usize = 64;
+
 /// Represents a Dimension in a Minecraft world
 pub struct Dimension<R> {
     /// The ID for the dimension, see [`DimensionID`]
     pub id: Option<DimensionID>,
     regions: HashMap<(i32, i32), R>,
+    region_cache: LruCache<(i32, i32), Box<Region>>,
+    chunk_cache: LruCache<(i32, i32), Option<ParsedChunk>>,
 }
 
 impl Dimension<RegionFile> {
@@ -473,7 +534,8 @@ where
     /// [`RegionParser`] trait.
     ///
     /// Every parser in the iterator must be able to determine a position, otherwise this call will
-    /// panic.
+    /// panic.  If an MCRegion and an anvil region have the same position, the anvil region is
+    /// kept, regardless of their order (see [`RegionParser::is_mcregion`]).
     ///
     /// Note: this call consumes the iterator, but does _not_ call [`RegionParser::parse`] on the
     /// items.
@@ -481,9 +543,25 @@ where
     where
         I: Iterator<Item = R>,
     {
+        let mut regions = HashMap::new();
+        for rf in iter {
+            match regions.entry(rf.position().unwrap()) {
+                Entry::Vacant(e) => {
+                    e.insert(rf);
+                }
+                Entry::Occupied(mut e) => {
+                    if !rf.is_mcregion() || e.get().is_mcregion() {
+                        e.insert(rf);
+                    }
+                }
+            }
+        }
+
         Self {
             id,
-            regions: iter.map(|rf| (rf.position().unwrap(), rf)).collect(),
+            regions,
+            region_cache: LruCache::new(REGION_CACHE_SIZE),
+            chunk_cache: LruCache::new(CHUNK_CACHE_SIZE),
         }
     }
 
@@ -498,7 +576,7 @@ where
     ///
     /// If the region does not exist in this Dimension, use [`Dimension::has_region`] to check
     /// before making a call to this method.
-    pub fn parse_region(&self, region_x: i32, region_z: i32) -> Result<RegionRef> {
+    pub fn parse_region(&self, region_x: i32, region_z: i32) -> Result<RegionRef<'_>> {
         self.regions[&(region_x, region_z)].parse()
     }
 
@@ -520,11 +598,14 @@ where
     /// - `Ok(None)` if the region does not exist
     /// - `Ok(Some(Region))` if the region exists and parsed successfully
     /// - `Err(_)` if the region failed to parse
-    pub fn get_region_from_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<Option<RegionRef>> {
-        // self.has_region(chunk_x / 32, chunk_z / 32)
-        //     .then(|| self.parse_region(chunk_x / 32, chunk_z / 32))
-        if self.has_region(chunk_x / 32, chunk_z / 32) {
-            Ok(Some(self.parse_region(chunk_x / 32, chunk_z / 32)?))
+    pub fn get_region_from_chunk(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Result<Option<RegionRef<'_>>> {
+        let (region_x, region_z) = (chunk_x.div_euclid(32), chunk_z.div_euclid(32));
+        if self.has_region(region_x, region_z) {
+            Ok(Some(self.parse_region(region_x, region_z)?))
         } else {
             Ok(None)
         }
@@ -563,4 +644,327 @@ where
             Err(e) => Err(e),
         }
     }
+
+    /// Parse every chunk in this dimension, one region at a time
+    ///
+    /// If a region or chunk fails to parse, the error is yielded and parsing continues with the
+    /// next region or chunk.
+    pub fn parse_chunks(&self) -> impl Iterator<Item = Result<ParsedChunk>> + '_ {
+        self.map_chunks(Chunk::parse)
+    }
+
+    /// Parse every chunk in this dimension as an entity chunk, one region at a time.  This is
+    /// for dimensions created from an `entities` directory, see [`nbt::EntityChunkNbt`].
+    ///
+    /// If a region or chunk fails to parse, the error is yielded and parsing continues with the
+    /// next region or chunk.
+    pub fn parse_entity_chunks(&self) -> impl Iterator<Item = Result<nbt::EntityChunkNbt>> + '_ {
+        self.map_chunks(Chunk::parse_entities)
+    }
+
+    /// Call `f` on every chunk in this dimension, one region at a time
+    fn map_chunks<'a, T: 'a>(
+        &'a self,
+        f: fn(&Chunk) -> Result<T>,
+    ) -> impl Iterator<Item = Result<T>> + 'a {
+        self.regions.values().flat_map(move |parser| {
+            let (region, err) = match parser.parse() {
+                Ok(region) => (Some(region), None),
+                Err(e) => (None, Some(Err(e))),
+            };
+
+            err.into_iter()
+                .chain(region.into_iter().flat_map(move |region| {
+                    (0..32 * 32).filter_map(move |i| {
+                        let chunk = region.get_chunk(i % 32, i / 32).transpose()?;
+                        Some(chunk.and_then(f))
+                    })
+                }))
+        })
+    }
+
+    /// Find the blocks in this dimension whose state matches `predicate` (i.e. every
+    /// `minecraft:spawner`), as absolute (x, y, z) coordinates in the world.
+    ///
+    /// Chunks are parsed one at a time as the iterator is advanced, and sections whose palette
+    /// has no matching entry are skipped without decoding their blocks, see
+    /// [`ParsedChunk::find_blocks`].  If a region or chunk fails to parse, the error is yielded
+    /// and the search continues with the next region or chunk.
+    ///
+    /// ```no_run
+    /// # use mca_parser::*;
+    /// let dim = Dimension::from_path("world/DIM-1/region")?;
+    /// let debris = nbt::NamespacedKey::from("ancient_debris");
+    /// for pos in dim.find_blocks(|state| state.name == debris) {
+    ///     println!("{:?}", pos?);
+    /// }
+    /// # Ok::<(), mca_parser::error::Error>(())
+    /// ```
+    pub fn find_blocks<'a, F>(
+        &'a self,
+        mut predicate: F,
+    ) -> impl Iterator<Item = Result<(i32, i32, i32)>> + 'a
+    where
+        F: FnMut(&nbt::BlockState) -> bool + 'a,
+    {
+        self.parse_chunks().flat_map(move |chunk| match chunk {
+            Ok(chunk) => chunk
+                .find_blocks(&mut predicate)
+                .into_iter()
+                .map(Ok)
+                .collect(),
+            Err(e) => vec![Err(e)],
+        })
+    }
+
+    /// Find every item stack in this dimension which matches `predicate`, by parsing every
+    /// chunk.  See [`ParsedChunk::find_items`] for where items are searched for.
+    ///
+    /// Chunks are parsed one at a time as the iterator is advanced.  If a region or chunk fails
+    /// to parse, the error is yielded and the search continues with the next region or chunk.
+    ///
+    /// Since 1.17, the entities of full chunks (such as dropped items and item frames) are stored
+    /// in a separate `entities` directory, which can be searched with
+    /// [`Dimension::find_entity_items`].
+    ///
+    /// ```no_run
+    /// # use mca_parser::*;
+    /// // Find every elytra in the overworld
+    /// let dim = Dimension::from_path("world/region")?;
+    /// let entities = Dimension::from_path("world/entities")?;
+    ///
+    /// let is_elytra = |item: &item::ItemStack| item.id == "minecraft:elytra".into();
+    /// let found = dim
+    ///     .find_items(is_elytra)
+    ///     .chain(entities.find_entity_items(is_elytra));
+    ///
+    /// for item in found {
+    ///     match item {
+    ///         Ok(item) => println!("{} at {:?} in {:?}", item.item.count, item.position, item.path),
+    ///         Err(e) => eprintln!("skipped a chunk: {:?}", e),
+    ///     }
+    /// }
+    /// # Ok::<(), mca_parser::error::Error>(())
+    /// ```
+    pub fn find_items<'a, F>(
+        &'a self,
+        mut predicate: F,
+    ) -> impl Iterator<Item = Result<item::FoundItem>> + 'a
+    where
+        F: FnMut(&item::ItemStack) -> bool + 'a,
+    {
+        self.parse_chunks().flat_map(move |chunk| match chunk {
+            Ok(chunk) => chunk
+                .find_items(&mut predicate)
+                .into_iter()
+                .map(Ok)
+                .collect(),
+            Err(e) => vec![Err(e)],
+        })
+    }
+
+    /// Find every item stack held by an entity in this dimension which matches `predicate`, for
+    /// dimensions created from an `entities` directory.  This includes dropped items, item frames
+    /// and minecarts with chests, and the items inside of those items.
+    ///
+    /// Like [`Dimension::find_items`], errors are yielded and the search continues with the next
+    /// region or chunk.
+    pub fn find_entity_items<'a, F>(
+        &'a self,
+        mut predicate: F,
+    ) -> impl Iterator<Item = Result<item::FoundItem>> + 'a
+    where
+        F: FnMut(&item::ItemStack) -> bool + 'a,
+    {
+        self.parse_entity_chunks()
+            .flat_map(move |chunk| match chunk {
+                Ok(chunk) => {
+                    let mut found = Vec::new();
+                    for entity in &chunk.entities {
+                        item::search_entity(entity, &mut predicate, &mut found);
+                    }
+                    found.into_iter().map(Ok).collect()
+                }
+                Err(e) => vec![Err(e)],
+            })
+    }
+
+    /// Find every structure which was generated in this dimension and has an id that `filter`
+    /// returns true for (i.e. `minecraft:village_plains`), by parsing every chunk.
+    ///
+    /// Chunks are parsed one at a time as the iterator is advanced.  If a region or chunk fails
+    /// to parse, the error is yielded and the search continues with the next region or chunk.
+    ///
+    /// Use [`structure::StructureStart::bounding_box`] to get the area that each structure
+    /// covers.
+    pub fn find_structures<'a, F>(
+        &'a self,
+        mut filter: F,
+    ) -> impl Iterator<Item = Result<structure::StructureStart>> + 'a
+    where
+        F: FnMut(&str) -> bool + 'a,
+    {
+        self.parse_chunks().flat_map(move |chunk| match chunk {
+            Ok(mut chunk) => std::mem::take(&mut chunk.structures)
+                .starts
+                .into_values()
+                .filter(|s| s.is_valid() && filter(&s.id))
+                .map(Ok)
+                .collect(),
+            Err(e) => vec![Err(e)],
+        })
+    }
+
+    /// Get a block from absolute block coordinates in the world (i.e. the "Block:" line in the F3
+    /// screen)
+    ///
+    /// Parsed regions and chunks are kept in a small least-recently-used cache, so repeated
+    /// lookups near each other only parse each region and chunk once.  Use
+    /// [`Dimension::clear_cache`] to free that memory.
+    ///
+    /// # Return Values
+    ///
+    /// - `Ok(None)` if the region or chunk does not exist, or the block is outside of the chunk's
+    ///   sections
+    /// - `Ok(Some(&BlockState))` if the block was found
+    /// - `Err(_)` if the region/chunk failed to parse
+    pub fn get_block(
+        &mut self,
+        block_x: i32,
+        block_y: i32,
+        block_z: i32,
+    ) -> Result<Option<&nbt::BlockState>> {
+        let chunk = self.get_cached_chunk(block_x.div_euclid(16), block_z.div_euclid(16))?;
+
+        Ok(chunk.and_then(|c| {
+            c.get_block(
+                positive_mod!(block_x, 16) as u32,
+                block_y,
+                positive_mod!(block_z, 16) as u32,
+            )
+        }))
+    }
+
+    /// Get the biome at absolute block coordinates in the world (i.e. the "Block:" line in the F3
+    /// screen)
+    ///
+    /// This uses the same cache as [`Dimension::get_block`].
+    ///
+    /// # Return Values
+    ///
+    /// - `Ok(None)` if the region or chunk does not exist, or the block is outside of the chunk's
+    ///   sections
+    /// - `Ok(Some(&str))` if the biome was found
+    /// - `Err(_)` if the region/chunk failed to parse
+    pub fn get_biome(&mut self, block_x: i32, block_y: i32, block_z: i32) -> Result<Option<&str>> {
+        let chunk = self.get_cached_chunk(block_x.div_euclid(16), block_z.div_euclid(16))?;
+
+        Ok(chunk.and_then(|c| {
+            c.get_biome(
+                positive_mod!(block_x, 16) as u32,
+                block_y,
+                positive_mod!(block_z, 16) as u32,
+            )
+        }))
+    }
+
+    /// Recompute the light of the chunk at an absolute chunk location, and of the chunks around it
+    /// so that light which crosses the chunk borders is correct on both sides.  See
+    /// [`light::relight_chunks`].
+    ///
+    /// The chunks are loaded through the same cache as [`Dimension::get_block`], along with the
+    /// chunks two chunks away which can also light the neighbours.  The relit chunks are returned
+    /// as copies, so the cached chunks are left as they are on disk.
+    ///
+    /// # Return Values
+    ///
+    /// - `Ok(None)` if the region or chunk does not exist
+    /// - `Ok(Some(Vec<ParsedChunk>))` with the relit chunk first, followed by each of its
+    ///   neighbours which exist
+    /// - `Err(_)` if the region/any of the chunks failed to parse
+    pub fn relight_chunk(
+        &mut self,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Result<Option<Vec<ParsedChunk>>> {
+        if self.get_cached_chunk(chunk_x, chunk_z)?.is_none() {
+            return Ok(None);
+        }
+
+        // The chunk itself comes first
+        let mut positions = vec![(chunk_x, chunk_z)];
+        for dx in -2..=2 {
+            for dz in -2..=2 {
+                if (dx, dz) != (0, 0) {
+                    let pos = (chunk_x + dx, chunk_z + dz);
+                    self.get_cached_chunk(pos.0, pos.1)?;
+                    positions.push(pos);
+                }
+            }
+        }
+
+        let cached = |pos: &(i32, i32)| self.chunk_cache.peek(pos).and_then(Option::as_ref);
+        let is_relit = |(x, z): &(i32, i32)| (x - chunk_x).abs() <= 1 && (z - chunk_z).abs() <= 1;
+
+        let mut chunks: Vec<_> = positions
+            .iter()
+            .filter(|pos| is_relit(pos))
+            .filter_map(|pos| cached(pos).cloned())
+            .collect();
+        let context: Vec<_> = positions
+            .iter()
+            .filter(|pos| !is_relit(pos))
+            .filter_map(cached)
+            .collect();
+
+        light::relight_chunks(&mut chunks, &context);
+
+        Ok(Some(chunks))
+    }
+
+    /// Drop all of the regions and chunks that have been cached by [`Dimension::get_block`] and
+    /// [`Dimension::get_biome`]
+    pub fn clear_cache(&mut self) {
+        self.region_cache.clear();
+        self.chunk_cache.clear();
+    }
+
+    /// Get a chunk from an absolute chunk location, going through the region and chunk caches
+    fn get_cached_chunk(&mut self, chunk_x: i32, chunk_z: i32) -> Result<Option<&ParsedChunk>> {
+        let key = (chunk_x, chunk_z);
+
+        if !self.chunk_cache.contains(&key) {
+            let chunk = self.load_chunk(chunk_x, chunk_z)?;
+            self.chunk_cache.insert(key, chunk);
+        }
+
+        Ok(self.chunk_cache.get(&key).and_then(Option::as_ref))
+    }
+
+    /// Parse a chunk from an absolute chunk location, going through the region cache
+    fn load_chunk(&mut self, chunk_x: i32, chunk_z: i32) -> Result<Option<ParsedChunk>> {
+        let region_key = (chunk_x.div_euclid(32), chunk_z.div_euclid(32));
+
+        if !self.region_cache.contains(&region_key) {
+            let Some(parser) = self.regions.get(&region_key) else {
+                return Ok(None);
+            };
+
+            let region = parser.parse()?.into_owned();
+            self.region_cache.insert(region_key, region);
+        }
+
+        let region = self
+            .region_cache
+            .get(&region_key)
+            .expect("region was just inserted");
+
+        match region.get_chunk(
+            positive_mod!(chunk_x, 32) as u32,
+            positive_mod!(chunk_z, 32) as u32,
+        )? {
+            Some(chunk) => Ok(Some(chunk.parse()?)),
+            None => Ok(None),
+        }
+    }
 }
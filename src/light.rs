@@ -0,0 +1,605 @@
+//! Offline light recomputation for chunks
+//!
+//! After editing blocks (i.e. with [`ParsedChunk::set_block`]), the light stored in the sections is
+//! stale, and clients will render black patches until the chunk is relit.  This module recomputes
+//! sky light and block light from the blocks in a chunk (and optionally its neighbours) and writes
+//! the result into the `BlockLight` and `SkyLight` arrays of each section.
+//!
+//! The emission and opacity of blocks come from a built-in table of vanilla blocks, see
+//! [`emission`] and [`opacity`].  Vanilla uses the shape of each block to decide how light passes
+//! through it, which this does not model, so non-full blocks (slabs, stairs, etc.) are treated as
+//! letting light through.
+//!
+//! - See <https://minecraft.wiki/w/Light>
+
+use std::collections::VecDeque;
+
+use fastnbt::ByteArray;
+
+use crate::{
+    nbt::{BlockState, Namespace},
+    ParsedChunk,
+};
+
+/// The highest light level
+const MAX_LIGHT: u8 = 15;
+
+/// Blocks which reduce light passing through them by 1, like water
+const DAMPENING_BLOCKS: &[&str] = &[
+    "water",
+    "bubble_column",
+    "ice",
+    "frosted_ice",
+    "cobweb",
+    "slime_block",
+    "honey_block",
+    "kelp",
+    "kelp_plant",
+    "seagrass",
+    "tall_seagrass",
+];
+
+/// Suffixes of block names which do not block light, i.e. `oak_slab` or `white_carpet`
+const TRANSPARENT_SUFFIXES: &[&str] = &[
+    "_slab",
+    "_stairs",
+    "_fence",
+    "_fence_gate",
+    "_wall",
+    "_door",
+    "_trapdoor",
+    "_pane",
+    "glass",
+    "_sign",
+    "_banner",
+    "_button",
+    "_pressure_plate",
+    "torch",
+    "lantern",
+    "rail",
+    "_carpet",
+    "candle",
+    "cake",
+    "_bed",
+    "_head",
+    "_skull",
+    "_sapling",
+    "_propagule",
+    "_coral",
+    "_coral_fan",
+    "_bud",
+    "_mushroom",
+    "_fungus",
+    "_roots",
+    "_vines",
+    "_plant",
+    "_tulip",
+    "_orchid",
+    "_petals",
+    "_rod",
+    "_dripleaf",
+    "_chest",
+];
+
+/// Blocks which do not block light and are not covered by [`TRANSPARENT_SUFFIXES`]
+const TRANSPARENT_BLOCKS: &[&str] = &[
+    "air",
+    "cave_air",
+    "void_air",
+    "light",
+    "barrier",
+    "structure_void",
+    "chest",
+    "ladder",
+    "vine",
+    "iron_bars",
+    "chain",
+    "short_grass",
+    "grass",
+    "tall_grass",
+    "fern",
+    "large_fern",
+    "dead_bush",
+    "dandelion",
+    "poppy",
+    "allium",
+    "azure_bluet",
+    "oxeye_daisy",
+    "cornflower",
+    "lily_of_the_valley",
+    "wither_rose",
+    "sunflower",
+    "lilac",
+    "rose_bush",
+    "peony",
+    "torchflower",
+    "sugar_cane",
+    "cactus",
+    "bamboo",
+    "lily_pad",
+    "snow",
+    "scaffolding",
+    "redstone_wire",
+    "repeater",
+    "comparator",
+    "lever",
+    "tripwire",
+    "tripwire_hook",
+    "flower_pot",
+    "anvil",
+    "chipped_anvil",
+    "damaged_anvil",
+    "hopper",
+    "cauldron",
+    "water_cauldron",
+    "lava_cauldron",
+    "powder_snow_cauldron",
+    "brewing_stand",
+    "enchanting_table",
+    "bell",
+    "campfire",
+    "soul_campfire",
+    "beacon",
+    "conduit",
+    "sea_pickle",
+    "pointed_dripstone",
+    "amethyst_cluster",
+    "spawner",
+    "turtle_egg",
+    "sniffer_egg",
+    "frogspawn",
+    "decorated_pot",
+    "lectern",
+    "grindstone",
+    "stonecutter",
+    "daylight_detector",
+    "glow_lichen",
+    "sculk_vein",
+    "spore_blossom",
+    "azalea",
+    "flowering_azalea",
+    "nether_sprouts",
+    "pumpkin_stem",
+    "melon_stem",
+    "attached_pumpkin_stem",
+    "attached_melon_stem",
+    "wheat",
+    "carrots",
+    "potatoes",
+    "beetroots",
+    "nether_wart",
+    "sweet_berry_bush",
+    "cocoa",
+    "end_portal",
+    "nether_portal",
+    "end_gateway",
+    "fire",
+    "soul_fire",
+    "piston_head",
+    "moving_piston",
+    "dragon_egg",
+];
+
+/// Blocks which match [`TRANSPARENT_SUFFIXES`], but are full blocks which block light
+const OPAQUE_EXCEPTIONS: &[&str] = &[
+    "tinted_glass",
+    "sea_lantern",
+    "jack_o_lantern",
+    "muddy_mangrove_roots",
+];
+
+/// Get the value of an integer property of a block state, or `0` if it is not set
+fn int_property(state: &BlockState, name: &str) -> u8 {
+    state.get_int(name).unwrap_or(0).clamp(0, u8::MAX as i32) as u8
+}
+
+/// Get the amount of light (`0..=15`) that a block state emits
+///
+/// Blocks which are not in the `minecraft` namespace do not emit light.
+pub fn emission(state: &BlockState) -> u8 {
+    if state.name.namespace != Namespace::Minecraft {
+        return 0;
+    }
+
+    let lit = state.get_bool("lit") == Some(true);
+    let key = state.name.key.as_str();
+
+    match key {
+        "beacon"
+        | "conduit"
+        | "end_gateway"
+        | "end_portal"
+        | "fire"
+        | "glowstone"
+        | "jack_o_lantern"
+        | "lava"
+        | "lantern"
+        | "sea_lantern"
+        | "shroomlight"
+        | "ochre_froglight"
+        | "verdant_froglight"
+        | "pearlescent_froglight" => 15,
+        "campfire" | "redstone_lamp" | "copper_bulb" | "waxed_copper_bulb" if lit => 15,
+        "exposed_copper_bulb" | "waxed_exposed_copper_bulb" if lit => 12,
+        "weathered_copper_bulb" | "waxed_weathered_copper_bulb" if lit => 8,
+        "oxidized_copper_bulb" | "waxed_oxidized_copper_bulb" if lit => 4,
+        "end_rod" | "torch" | "wall_torch" => 14,
+        "cave_vines" | "cave_vines_plant" if state.get_bool("berries") == Some(true) => 14,
+        "furnace" | "blast_furnace" | "smoker" if lit => 13,
+        "nether_portal" => 11,
+        "crying_obsidian" | "soul_fire" | "soul_lantern" | "soul_torch" | "soul_wall_torch" => 10,
+        "soul_campfire" if lit => 10,
+        "redstone_ore" | "deepslate_redstone_ore" if lit => 9,
+        "enchanting_table" | "ender_chest" | "glow_lichen" => 7,
+        "redstone_torch" | "redstone_wall_torch" if lit => 7,
+        "sculk_catalyst" => 6,
+        "amethyst_cluster" => 5,
+        "large_amethyst_bud" => 4,
+        "magma_block" => 3,
+        "medium_amethyst_bud" => 2,
+        "brewing_stand"
+        | "brown_mushroom"
+        | "dragon_egg"
+        | "end_portal_frame"
+        | "small_amethyst_bud"
+        | "sculk_sensor"
+        | "calibrated_sculk_sensor" => 1,
+        "light" => int_property(state, "level").min(MAX_LIGHT),
+        "respawn_anchor" => match int_property(state, "charges") {
+            0 => 0,
+            charges => (charges * 4 - 1).min(MAX_LIGHT),
+        },
+        "sea_pickle" if state.get_bool("waterlogged") == Some(true) => {
+            (int_property(state, "pickles") * 3 + 3).min(MAX_LIGHT)
+        }
+        _ if lit && key.ends_with("candle") => (int_property(state, "candles") * 3).min(MAX_LIGHT),
+        _ => 0,
+    }
+}
+
+/// Get the amount (`0..=15`) that a block state reduces light by when it passes through it.  Light
+/// is always reduced by at least 1 per block when it spreads, except for sky light going straight
+/// down.
+///
+/// - `0` for blocks which light passes through freely, like air, glass, or slabs
+/// - `1` for blocks which dampen light, like water, ice, or leaves (and waterlogged blocks)
+/// - `15` for every other block, including all blocks which are not in the `minecraft`
+///   namespace
+pub fn opacity(state: &BlockState) -> u8 {
+    if state.name.namespace != Namespace::Minecraft {
+        return MAX_LIGHT;
+    }
+
+    let key = state.name.key.as_str();
+
+    if DAMPENING_BLOCKS.contains(&key)
+        || key.ends_with("_leaves")
+        || state.get_bool("waterlogged") == Some(true)
+    {
+        1
+    } else if OPAQUE_EXCEPTIONS.contains(&key) {
+        MAX_LIGHT
+    } else if TRANSPARENT_BLOCKS.contains(&key)
+        || key.starts_with("potted_")
+        || TRANSPARENT_SUFFIXES.iter().any(|s| key.ends_with(s))
+    {
+        0
+    } else {
+        MAX_LIGHT
+    }
+}
+
+/// A box of blocks spanning one or more chunks, used to propagate light across chunk borders
+struct LightVolume {
+    /// Chunk coordinates of the chunk at the lowest x and z in the volume
+    min_chunk: (i32, i32),
+    /// Size of the volume in chunks along x and z
+    chunks: (usize, usize),
+    /// Section y-value of the lowest section in the volume
+    min_section: i8,
+    /// Number of sections in the volume
+    sections: usize,
+    opacity: Vec<u8>,
+    emission: Vec<u8>,
+    /// Whether each column has a chunk in it, sky light only comes down into columns that do
+    present: Vec<bool>,
+    sky_light: Vec<u8>,
+    block_light: Vec<u8>,
+}
+
+impl LightVolume {
+    fn new(chunks: &[&ParsedChunk], min_section: i8, sections: usize) -> Self {
+        let min_x = chunks.iter().map(|c| c.x_pos).min().unwrap_or(0);
+        let min_z = chunks.iter().map(|c| c.z_pos).min().unwrap_or(0);
+        let max_x = chunks.iter().map(|c| c.x_pos).max().unwrap_or(0);
+        let max_z = chunks.iter().map(|c| c.z_pos).max().unwrap_or(0);
+
+        let size = ((max_x - min_x + 1) as usize, (max_z - min_z + 1) as usize);
+        let len = size.0 * 16 * size.1 * 16 * sections * 16;
+
+        let mut volume = Self {
+            min_chunk: (min_x, min_z),
+            chunks: size,
+            min_section,
+            sections,
+            // Anything without a chunk is treated as solid
+            opacity: vec![MAX_LIGHT; len],
+            emission: vec![0; len],
+            present: vec![false; size.0 * 16 * size.1 * 16],
+            sky_light: vec![0; len],
+            block_light: vec![0; len],
+        };
+
+        for chunk in chunks {
+            volume.load_chunk(chunk);
+        }
+
+        volume
+    }
+
+    fn width(&self) -> usize {
+        self.chunks.0 * 16
+    }
+
+    fn depth(&self) -> usize {
+        self.chunks.1 * 16
+    }
+
+    fn height(&self) -> usize {
+        self.sections * 16
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (y * self.depth() + z) * self.width() + x
+    }
+
+    /// Get the offset of a chunk's lowest x and z within the volume
+    fn chunk_offset(&self, chunk: &ParsedChunk) -> (usize, usize) {
+        (
+            (chunk.x_pos - self.min_chunk.0) as usize * 16,
+            (chunk.z_pos - self.min_chunk.1) as usize * 16,
+        )
+    }
+
+    /// Get the y offset of a section within the volume, if it is inside the volume
+    fn section_offset(&self, section_y: i8) -> Option<usize> {
+        let offset = section_y as i32 - self.min_section as i32;
+        (0..self.sections as i32)
+            .contains(&offset)
+            .then_some(offset as usize * 16)
+    }
+
+    fn load_chunk(&mut self, chunk: &ParsedChunk) {
+        let (ox, oz) = self.chunk_offset(chunk);
+
+        for z in 0..16 {
+            for x in 0..16 {
+                let column = (oz + z) * self.width() + ox + x;
+                self.present[column] = true;
+
+                // Sections which are missing from the chunk are air
+                for y in 0..self.height() {
+                    let i = self.index(ox + x, y, oz + z);
+                    self.opacity[i] = 0;
+                }
+            }
+        }
+
+        for section in &chunk.sections {
+            let Some(oy) = self.section_offset(section.y) else {
+                continue;
+            };
+            let Some(block_states) = &section.block_states else {
+                continue;
+            };
+
+            let materials: Vec<_> = block_states
+                .palette
+                .iter()
+                .map(|b| (opacity(b), emission(b)))
+                .collect();
+
+            for (i, palette_index) in block_states.palette_indices().into_iter().enumerate() {
+                let (x, y, z) = (i % 16, i / 256, (i / 16) % 16);
+                let (opacity, emission) = materials
+                    .get(palette_index as usize)
+                    .copied()
+                    .unwrap_or_default();
+
+                let i = self.index(ox + x, oy + y, oz + z);
+                self.opacity[i] = opacity;
+                self.emission[i] = emission;
+            }
+        }
+    }
+
+    /// Call `f` with the index of every neighbour of `i` that is inside the volume
+    fn neighbours(&self, i: usize, mut f: impl FnMut(usize)) {
+        let (width, depth) = (self.width(), self.depth());
+        let x = i % width;
+        let z = (i / width) % depth;
+        let y = i / (width * depth);
+
+        if x > 0 {
+            f(i - 1);
+        }
+        if x + 1 < width {
+            f(i + 1);
+        }
+        if z > 0 {
+            f(i - width);
+        }
+        if z + 1 < depth {
+            f(i + width);
+        }
+        if y > 0 {
+            f(i - width * depth);
+        }
+        if y + 1 < self.height() {
+            f(i + width * depth);
+        }
+    }
+
+    /// Spread light out from every cell in `queue`, reducing it by the opacity of each block it
+    /// passes through (and at least 1 per block)
+    fn propagate(&self, light: &mut [u8], mut queue: VecDeque<usize>) {
+        while let Some(i) = queue.pop_front() {
+            let level = light[i];
+            if level <= 1 {
+                continue;
+            }
+
+            self.neighbours(i, |n| {
+                let new = level.saturating_sub(self.opacity[n].max(1));
+                if new > light[n] {
+                    light[n] = new;
+                    queue.push_back(n);
+                }
+            });
+        }
+    }
+
+    fn compute_sky_light(&mut self) {
+        let mut light = vec![0; self.sky_light.len()];
+        let mut queue = VecDeque::new();
+
+        // Sky light comes straight down from the top of the world without losing any light until
+        // it hits a block which dampens it
+        for z in 0..self.depth() {
+            for x in 0..self.width() {
+                if !self.present[z * self.width() + x] {
+                    continue;
+                }
+
+                let mut level = MAX_LIGHT;
+                for y in (0..self.height()).rev() {
+                    let i = self.index(x, y, z);
+                    level = level.saturating_sub(self.opacity[i]);
+                    if level == 0 {
+                        break;
+                    }
+
+                    light[i] = level;
+                    queue.push_back(i);
+                }
+            }
+        }
+
+        self.propagate(&mut light, queue);
+        self.sky_light = light;
+    }
+
+    fn compute_block_light(&mut self) {
+        let mut light = self.emission.clone();
+        let queue = (0..light.len()).filter(|i| light[*i] > 0).collect();
+
+        self.propagate(&mut light, queue);
+        self.block_light = light;
+    }
+
+    /// Write the light in this volume into the sections of `chunk`
+    fn write_chunk(&self, chunk: &mut ParsedChunk) {
+        let (ox, oz) = self.chunk_offset(chunk);
+
+        for section in chunk.sections.iter_mut() {
+            let Some(oy) = self.section_offset(section.y) else {
+                continue;
+            };
+
+            let mut sky_light = vec![0i8; 2048];
+            let mut block_light = vec![0i8; 2048];
+
+            for i in 0..4096 {
+                let (x, y, z) = (i % 16, i / 256, (i / 16) % 16);
+                let v = self.index(ox + x, oy + y, oz + z);
+                let shift = (i % 2) * 4;
+
+                sky_light[i / 2] |= (self.sky_light[v] << shift) as i8;
+                block_light[i / 2] |= (self.block_light[v] << shift) as i8;
+            }
+
+            section.sky_light = Some(ByteArray::new(sky_light));
+            section.block_light = Some(ByteArray::new(block_light));
+        }
+
+        chunk.is_light_on = Some(true);
+    }
+}
+
+/// Recompute the sky light and block light of `chunk`, and write it into its sections.
+///
+/// `neighbours` are other chunks which are used to light the blocks near the borders of
+/// `chunk`, any chunks which are not directly next to `chunk` (including diagonally) are ignored.
+/// Pass an empty slice to light `chunk` as if it were the only chunk in the world.
+///
+/// The light in `neighbours` is not changed, since blocks in `chunk` may light blocks in the
+/// neighbours which are next to chunks that are not provided.  Use [`relight_chunks`] to update
+/// the light of several chunks together.
+pub fn relight(chunk: &mut ParsedChunk, neighbours: &[&ParsedChunk]) {
+    relight_chunks(std::slice::from_mut(chunk), neighbours);
+}
+
+/// Recompute the sky light and block light of every chunk in `chunks` together, so that light
+/// which crosses the borders between them is written into all of them.
+///
+/// `context` are other chunks which are only used to light the blocks near the borders of
+/// `chunks`, and their light is not changed.  Chunks in `context` which are not directly next to
+/// one of `chunks` (including diagonally) are ignored.  Since light spreads up to 15 blocks, the
+/// light of a chunk is only correct if all of the chunks around it are in `chunks` or `context`.
+pub fn relight_chunks(chunks: &mut [ParsedChunk], context: &[&ParsedChunk]) {
+    let (Some(min_section), Some(max_section)) = (
+        chunks.iter().flat_map(|c| &c.sections).map(|s| s.y).min(),
+        chunks.iter().flat_map(|c| &c.sections).map(|s| s.y).max(),
+    ) else {
+        return;
+    };
+
+    let is_next_to = |n: &ParsedChunk, c: &ParsedChunk| {
+        (n.x_pos - c.x_pos).abs() <= 1 && (n.z_pos - c.z_pos).abs() <= 1
+    };
+
+    let mut all: Vec<&ParsedChunk> = chunks.iter().collect();
+    all.extend(context.iter().copied().filter(|n| {
+        chunks
+            .iter()
+            .all(|c| (n.x_pos, n.z_pos) != (c.x_pos, c.z_pos))
+            && chunks.iter().any(|c| is_next_to(n, c))
+    }));
+
+    let sections = (max_section as i32 - min_section as i32 + 1) as usize;
+    let mut volume = LightVolume::new(&all, min_section, sections);
+    volume.compute_sky_light();
+    volume.compute_block_light();
+
+    for chunk in chunks {
+        volume.write_chunk(chunk);
+    }
+}
+
+#[test]
+fn test_materials() {
+    fn state(s: &str) -> BlockState {
+        s.parse().unwrap()
+    }
+
+    assert_eq!(opacity(&state("air")), 0);
+    assert_eq!(opacity(&state("stone")), 15);
+    assert_eq!(opacity(&state("grass_block")), 15);
+    assert_eq!(opacity(&state("short_grass")), 0);
+    assert_eq!(opacity(&state("glass")), 0);
+    assert_eq!(opacity(&state("tinted_glass")), 15);
+    assert_eq!(opacity(&state("oak_leaves")), 1);
+    assert_eq!(opacity(&state("water")), 1);
+    assert_eq!(opacity(&state("mod:thing")), 15);
+    assert_eq!(opacity(&state("oak_stairs[waterlogged=true]")), 1);
+    assert_eq!(opacity(&state("lantern")), 0);
+    assert_eq!(opacity(&state("soul_lantern")), 0);
+    assert_eq!(opacity(&state("jack_o_lantern")), 15);
+
+    assert_eq!(emission(&state("glowstone")), 15);
+    assert_eq!(emission(&state("torch")), 14);
+    assert_eq!(emission(&state("furnace")), 0);
+    assert_eq!(emission(&state("furnace[lit=true]")), 13);
+    assert_eq!(emission(&state("white_candle[lit=true,candles=4]")), 12);
+    assert_eq!(emission(&state("light[level=7]")), 7);
+}
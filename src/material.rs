@@ -0,0 +1,145 @@
+//! Classification of vanilla block states, used when recomputing data that depends on the kind of
+//! block, like heightmaps.
+//!
+//! These are built-in approximations of vanilla's block properties, blocks which are not in the
+//! `minecraft` namespace are treated as solid, non-fluid blocks.
+
+use crate::nbt::{BlockState, Namespace};
+
+/// Blocks which do not have a collision box, and are not covered by [`NON_SOLID_SUFFIXES`]
+const NON_SOLID_BLOCKS: &[&str] = &[
+    "air",
+    "cave_air",
+    "void_air",
+    "light",
+    "structure_void",
+    "water",
+    "lava",
+    "bubble_column",
+    "cobweb",
+    "vine",
+    "short_grass",
+    "grass",
+    "tall_grass",
+    "fern",
+    "large_fern",
+    "dead_bush",
+    "dandelion",
+    "poppy",
+    "allium",
+    "azure_bluet",
+    "oxeye_daisy",
+    "cornflower",
+    "lily_of_the_valley",
+    "wither_rose",
+    "sunflower",
+    "lilac",
+    "rose_bush",
+    "peony",
+    "torchflower",
+    "pitcher_plant",
+    "sugar_cane",
+    "snow",
+    "redstone_wire",
+    "lever",
+    "tripwire",
+    "tripwire_hook",
+    "seagrass",
+    "tall_seagrass",
+    "kelp",
+    "kelp_plant",
+    "glow_lichen",
+    "sculk_vein",
+    "spore_blossom",
+    "hanging_roots",
+    "nether_sprouts",
+    "pink_petals",
+    "small_dripleaf",
+    "big_dripleaf_stem",
+    "sweet_berry_bush",
+    "wheat",
+    "carrots",
+    "potatoes",
+    "beetroots",
+    "nether_wart",
+    "pumpkin_stem",
+    "melon_stem",
+    "attached_pumpkin_stem",
+    "attached_melon_stem",
+    "fire",
+    "soul_fire",
+    "nether_portal",
+    "end_portal",
+    "end_gateway",
+    "frogspawn",
+];
+
+/// Suffixes of block names which do not have a collision box, i.e. `oak_sign` or `oak_sapling`
+const NON_SOLID_SUFFIXES: &[&str] = &[
+    "_sign",
+    "_banner",
+    "_button",
+    "_pressure_plate",
+    "torch",
+    "rail",
+    "_sapling",
+    "_propagule",
+    "_coral",
+    "_coral_fan",
+    "_mushroom",
+    "_fungus",
+    "_tulip",
+    "_orchid",
+    "_vines",
+    "_vines_plant",
+];
+
+/// Blocks which match [`NON_SOLID_SUFFIXES`], but do have a collision box
+const SOLID_EXCEPTIONS: &[&str] = &["mangrove_roots", "muddy_mangrove_roots"];
+
+/// Blocks which always contain water, even though they don't have a `waterlogged` property
+const WATER_BLOCKS: &[&str] = &[
+    "water",
+    "bubble_column",
+    "seagrass",
+    "tall_seagrass",
+    "kelp",
+    "kelp_plant",
+];
+
+/// Get the key of a block state if it is in the `minecraft` namespace
+fn vanilla_key(state: &BlockState) -> Option<&str> {
+    (state.name.namespace == Namespace::Minecraft).then_some(state.name.key.as_str())
+}
+
+/// Check if a block state is one of the kinds of air
+pub(crate) fn is_air(state: &BlockState) -> bool {
+    matches!(vanilla_key(state), Some("air" | "cave_air" | "void_air"))
+}
+
+/// Check if a block state blocks motion (i.e. has a collision box), for the purposes of
+/// heightmaps.  Carpets are not considered to have a collision box.
+pub(crate) fn blocks_motion(state: &BlockState) -> bool {
+    let Some(key) = vanilla_key(state) else {
+        return true;
+    };
+
+    if SOLID_EXCEPTIONS.contains(&key) {
+        return true;
+    }
+
+    !(NON_SOLID_BLOCKS.contains(&key)
+        || key.ends_with("_carpet")
+        || NON_SOLID_SUFFIXES.iter().any(|s| key.ends_with(s)))
+}
+
+/// Check if a block state contains a fluid, either because it is a fluid or it is waterlogged
+pub(crate) fn has_fluid(state: &BlockState) -> bool {
+    matches!(vanilla_key(state), Some(key) if key == "lava" || WATER_BLOCKS.contains(&key))
+        || state.get_bool("waterlogged") == Some(true)
+}
+
+/// Check if a block state is a kind of leaves
+pub(crate) fn is_leaves(state: &BlockState) -> bool {
+    matches!(vanilla_key(state), Some(key) if key.ends_with("_leaves"))
+}
@@ -12,11 +12,19 @@
 //! every field in this module_ just to make it happy and you'll be just as annoyed as I am!  
 //! &lt;/rant&gt;
 
-use fastnbt::{self, LongArray, Value};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use fastnbt::{self, ByteArray, IntArray, LongArray, Value};
 use serde::Deserialize;
 
+use crate::packed::{
+    get_item_in_packed_slice, normalize_palette, pack, palette_bits, set_item_in_packed_slice,
+    unpack, unpack_spanning,
+};
+use crate::{block_entity::BlockEntity, error::Error, structure::Structures, version};
+
 /// Represents a namespace that can show up in the game
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Namespace {
     /// Default namespace for every vanilla item/block/etc
     Minecraft,
@@ -35,7 +43,7 @@ impl From<&str> for Namespace {
 }
 
 /// A struct which represents a key with a namespace
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct NamespacedKey {
     /// The namespace of this key
     pub namespace: Namespace,
@@ -71,6 +79,21 @@ impl From<&str> for NamespacedKey {
     }
 }
 
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Namespace::Minecraft => write!(f, "minecraft"),
+            Namespace::Custom(ns) => write!(f, "{}", ns),
+        }
+    }
+}
+
+impl fmt::Display for NamespacedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.key)
+    }
+}
+
 impl<'de> serde::Deserialize<'de> for NamespacedKey {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
@@ -97,45 +120,73 @@ pub struct ChunkNbt {
     /// Lowest Y section in chunk
     #[serde(rename = "yPos")]
     pub y_pos: i32,
-    /// Defines the world generation status of this chunk
+    /// Defines the world generation status of this chunk, use [`ChunkNbt::chunk_status`] to get
+    /// it as a [`ChunkStatus`]
     ///
-    /// All status except [`Status::Full`] are used for chunks called proto-chunks, in other words,
-    /// for chunks with incomplete generation.
+    /// All status except [`ChunkStatus::Full`] are used for chunks called proto-chunks, in other
+    /// words, for chunks with incomplete generation.
     #[serde(rename = "Status")]
     pub status: NamespacedKey,
     /// Tick when the chunk was last saved.
     #[serde(rename = "LastUpdate")]
     pub last_update: i64,
     /// List of block entities in this chunk
-    pub block_entities: Vec<Value>, // TODO: Can probably be replaced with an enum
+    pub block_entities: Vec<BlockEntity>,
+    /// List of entities in this chunk.  Since 1.17, only proto-chunks store their entities here,
+    /// and the entities of full chunks are stored in separate region files, see
+    /// [`EntityChunkNbt`].
+    #[serde(default)]
+    pub entities: Vec<Value>,
     /// Several different heightmaps corresponding to 256 values compacted at 9 bits per value
-    /// (lowest being 0, highest being 384, both values inclusive).
+    /// (lowest being 0, highest being 384, both values inclusive) in the overworld.  Dimensions
+    /// with custom heights use a different number of bits, see [`WorldHeight`].
     #[serde(rename = "Heightmaps")]
     pub height_maps: HeightMaps,
     /// List of "active" liquids in this chunk waiting to be updated
-    pub fluid_ticks: Vec<Value>, // - See Tile Tick Format
+    pub fluid_ticks: Vec<TileTick>,
     /// List of "active" blocks in this chunk waiting to be updated. These are used to save the
     /// state of redstone machines or falling sand, and other activity
-    pub block_ticks: Vec<Value>,
+    pub block_ticks: Vec<TileTick>,
     ///  The cumulative number of ticks players have been in this chunk. Note that this value
     ///  increases faster when more players are in the chunk. Used for Regional Difficulty.
     #[serde(rename = "InhabitedTime")]
     pub inhabited_time: i64,
+    /// Whether the light data in this chunk has been calculated.  If this is not `true`, the
+    /// light values in the sections are not valid.
+    #[serde(rename = "isLightOn")]
+    pub is_light_on: Option<bool>,
     /// This appears to be biome blending data, although more testing is needed to confirm.
     pub blending_data: Option<BlendingData>,
-    /// A List of 24  Lists that store the positions of blocks that need to receive an update when
-    /// a proto-chunk turns into a full chunk, packed in  Shorts. Each list corresponds to specific
-    /// section in the height of the chunk
+    /// A List of Lists (one for each section, so 24 in the overworld) that store the positions of
+    /// blocks that need to receive an update when a proto-chunk turns into a full chunk, packed in
+    /// Shorts. Each list corresponds to specific section in the height of the chunk
     #[serde(rename = "PostProcessing")]
-    pub post_processing: [Vec<Value>; 24],
+    pub post_processing: Vec<Vec<Value>>,
     /// Structure data in this chunk
-    pub structures: Value,
+    pub structures: Structures,
     /// A list of the sections in this chunk
     ///
     /// All sections in the world's height are present in this list, even those who are empty (filled with air).
     pub sections: Vec<ChunkSection>,
 }
 
+/// The nbt data of a chunk in an entity region file (in the `entities` directory of a dimension),
+/// which is where the entities of full chunks are stored since 1.17
+///
+/// - See <https://minecraft.wiki/w/Entity_format#Entity_chunk_format>
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EntityChunkNbt {
+    /// Version of the chunk NBT structure.
+    #[serde(rename = "DataVersion")]
+    pub data_version: i32,
+    /// The position of the chunk, as `[x, z]` in absolute chunks
+    #[serde(rename = "Position")]
+    pub position: IntArray,
+    /// List of entities in this chunk
+    #[serde(rename = "Entities", default)]
+    pub entities: Vec<Value>,
+}
+
 /// From the wiki: This appears to be biome blending data, although more testing is needed to confirm.
 ///
 /// - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>
@@ -152,7 +203,7 @@ pub struct BlendingData {
 ///
 /// - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>  
 /// - See <https://minecraft.wiki/w/Heightmap>
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub struct HeightMaps {
     /// Stores the Y-level of the highest block whose material blocks motion (i.e. has a collision
@@ -177,34 +228,303 @@ pub struct HeightMaps {
     pub world_surface_wg: Option<HeightMap>,
 }
 
+impl HeightMaps {
+    /// Iterate over all of the heightmaps which are present
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut HeightMap> {
+        [
+            &mut self.motion_blocking,
+            &mut self.motion_blocking_no_leaves,
+            &mut self.ocean_floor,
+            &mut self.ocean_floor_wg,
+            &mut self.world_surface,
+            &mut self.world_surface_wg,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
 /// Wrapper type around a [`LongArray`] to abstract away the details of how the HeightMaps store
 /// their data
 ///
 /// Several different heightmaps corresponding to 256 values compacted at 9 bits per value (lowest
-/// being 0, highest being 384, both values inclusive).
+/// being 0, highest being 384, both values inclusive) in the overworld.
+///
+/// In dimensions with custom heights, the number of bits is the minimum needed to store the
+/// height of the world, so the [`WorldHeight`] of the chunk is needed to decode the values.
 ///
 /// - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>  
 /// - See <https://minecraft.wiki/w/Heightmap>
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(transparent)]
 pub struct HeightMap {
-    /// The 9-bit values are stored in an array of 37 Longs ([`u64`]), each containing 7 values (7×9 =
-    /// 63; the last bit is unused). The 9-bit values are unsigned, and indicate the amount of blocks
-    /// above the bottom of the world (y = -64).
+    /// In the overworld, the 9-bit values are stored in an array of 37 Longs ([`u64`]), each
+    /// containing 7 values (7×9 = 63; the last bit is unused). The values are unsigned, and indicate
+    /// the amount of blocks above the bottom of the world (y = -64 in the overworld).
     raw: LongArray,
 }
 
+/// The range of y values that blocks can be in for a chunk, which heightmaps are relative to
+///
+/// In the overworld, this is `-64..320` but data packs can change the height of a dimension, see
+/// [`ChunkNbt::world_height`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct WorldHeight {
+    /// The y value of the lowest block in the world
+    pub min_y: i32,
+    /// The number of blocks between the lowest and highest y values
+    pub height: u32,
+}
+
+impl WorldHeight {
+    /// The height of the overworld since 1.18, `y = -64` to `y = 319`
+    pub const OVERWORLD: Self = Self {
+        min_y: -64,
+        height: 384,
+    };
+
+    /// Get the number of bits used for each value in a heightmap, which needs to store the values
+    /// `0..=height`
+    pub fn heightmap_bits(&self) -> u32 {
+        palette_bits(self.height as usize + 1, 1)
+    }
+}
+
+impl ChunkNbt {
+    /// Get the name of the release which saved this chunk, using [`version::latest_release_at`]
+    /// so that chunks saved by snapshots give the release before them
+    pub fn saved_by(&self) -> Option<&'static str> {
+        version::latest_release_at(self.data_version)
+    }
+
+    /// Get the generation status of this chunk, see [`ChunkStatus::from_key`].  Returns [`None`]
+    /// if the status is not known.
+    pub fn chunk_status(&self) -> Option<ChunkStatus> {
+        ChunkStatus::from_key(&self.status)
+    }
+
+    /// Check if this chunk is a proto-chunk, which has not finished generating.  Chunks with an
+    /// unknown status are treated as proto-chunks.
+    pub fn is_proto_chunk(&self) -> bool {
+        self.chunk_status() != Some(ChunkStatus::Full)
+    }
+
+    /// Get the range of y values that blocks can be in for this chunk, which spans from `yPos` to
+    /// the highest section with block states.  Sections which are missing below that section, or
+    /// which only store light, don't change the height.
+    ///
+    /// Chunks which are missing their highest sections (i.e. trimmed proto-chunks) can't be told
+    /// apart from chunks in a shorter world, so use the height of the dimension instead when it
+    /// is known, i.e. with [`crate::ParsedChunk::recompute_height_maps_in`].
+    pub fn world_height(&self) -> WorldHeight {
+        let top = self
+            .sections
+            .iter()
+            .filter(|s| s.block_states.is_some())
+            .map(|s| s.y as i32)
+            .max();
+        let sections = top.map_or(0, |top| (top - self.y_pos + 1).max(0));
+
+        WorldHeight {
+            min_y: self.y_pos * 16,
+            height: sections as u32 * 16,
+        }
+    }
+}
+
+/// The generation status of a chunk, in the order that the stages are run.  Chunks at any status
+/// other than [`ChunkStatus::Full`] are proto-chunks.
+///
+/// Some statuses only exist in some versions, i.e. `liquid_carvers` was removed in 1.19 and
+/// `initialize_light` was added in 1.20.5.
+///
+/// - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>
+/// - See <https://minecraft.wiki/w/World_generation#Steps>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkStatus {
+    /// Nothing has been generated
+    Empty,
+    /// The starting points of structures have been placed
+    StructureStarts,
+    /// References to structures in nearby chunks have been stored
+    StructureReferences,
+    /// The biomes have been decided
+    Biomes,
+    /// The terrain shape and fluids have been generated
+    Noise,
+    /// The surface blocks (such as grass and sand) have been placed
+    Surface,
+    /// Caves have been carved
+    Carvers,
+    /// Underwater caves have been carved (before 1.19)
+    LiquidCarvers,
+    /// Features (such as trees and ores) and structures have been placed
+    Features,
+    /// The lighting engine has been set up for the chunk (since 1.20.5)
+    InitializeLight,
+    /// The light has been calculated
+    Light,
+    /// Mobs have been spawned
+    Spawn,
+    /// The heightmaps have been calculated (before 1.20)
+    Heightmaps,
+    /// The chunk has finished generating
+    Full,
+}
+
+impl ChunkStatus {
+    /// Every status, in the order that they are run
+    pub const ALL: [ChunkStatus; 14] = [
+        Self::Empty,
+        Self::StructureStarts,
+        Self::StructureReferences,
+        Self::Biomes,
+        Self::Noise,
+        Self::Surface,
+        Self::Carvers,
+        Self::LiquidCarvers,
+        Self::Features,
+        Self::InitializeLight,
+        Self::Light,
+        Self::Spawn,
+        Self::Heightmaps,
+        Self::Full,
+    ];
+
+    /// Get the status from the value of the `Status` tag of a chunk (i.e. `minecraft:full`).
+    /// The statuses used before 1.14 are converted the same way as the game does when it
+    /// upgrades a chunk (i.e. `postprocessed` is [`ChunkStatus::Full`]).
+    ///
+    /// Returns [`None`] if the status is not a known vanilla status.
+    pub fn from_key(key: &NamespacedKey) -> Option<Self> {
+        if key.namespace != Namespace::Minecraft {
+            return None;
+        }
+
+        Some(match key.key.as_str() {
+            "empty" => Self::Empty,
+            "structure_starts" => Self::StructureStarts,
+            "structure_references" => Self::StructureReferences,
+            "biomes" => Self::Biomes,
+            "noise" => Self::Noise,
+            "surface" | "base" => Self::Surface,
+            "carvers" | "carved" => Self::Carvers,
+            "liquid_carvers" | "liquid_carved" => Self::LiquidCarvers,
+            "features" | "decorated" => Self::Features,
+            "initialize_light" => Self::InitializeLight,
+            "light" | "lighted" => Self::Light,
+            "spawn" | "mobs_spawned" => Self::Spawn,
+            "heightmaps" | "finalized" => Self::Heightmaps,
+            "full" | "postprocessed" | "fullchunk" => Self::Full,
+            _ => return None,
+        })
+    }
+
+    /// Get the name of this status, as it is stored since 1.14 (without the namespace)
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::StructureStarts => "structure_starts",
+            Self::StructureReferences => "structure_references",
+            Self::Biomes => "biomes",
+            Self::Noise => "noise",
+            Self::Surface => "surface",
+            Self::Carvers => "carvers",
+            Self::LiquidCarvers => "liquid_carvers",
+            Self::Features => "features",
+            Self::InitializeLight => "initialize_light",
+            Self::Light => "light",
+            Self::Spawn => "spawn",
+            Self::Heightmaps => "heightmaps",
+            Self::Full => "full",
+        }
+    }
+}
+
+impl From<ChunkStatus> for NamespacedKey {
+    fn from(value: ChunkStatus) -> Self {
+        NamespacedKey::minecraft(value.name().into())
+    }
+}
+
+impl fmt::Display for ChunkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", NamespacedKey::from(*self))
+    }
+}
+
 impl HeightMap {
-    /// Get the height of a chunk using this heightmap at a positon (relative to the chunk)
-    pub fn get_height(&self, block_x: u32, block_z: u32) -> i32 {
+    /// Create a heightmap from the y value of the highest block in each column of a chunk, where
+    /// `heights` is indexed by `z * 16 + x`.
+    ///
+    /// Columns without any blocks have a height of `world.min_y - 1`.
+    pub fn new(heights: &[i32; 256], world: WorldHeight) -> Self {
+        let values: Vec<_> = heights
+            .iter()
+            .map(|y| Self::height_to_value(*y, world))
+            .collect();
+
+        Self {
+            raw: LongArray::new(pack(&values, world.heightmap_bits())),
+        }
+    }
+
+    /// Set the height of a column of a chunk in this heightmap at a position (relative to the
+    /// chunk), where `block_y` is the y value of the highest block in the column.
+    ///
+    /// `world` must be the same as the one used to create this heightmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this heightmap is too short for `world`, see [`HeightMap::get_height`].
+    pub fn set_height(&mut self, block_x: u32, block_z: u32, block_y: i32, world: WorldHeight) {
+        set_item_in_packed_slice(
+            &mut self.raw,
+            Self::index(block_x, block_z),
+            world.heightmap_bits(),
+            Self::height_to_value(block_y, world),
+        );
+    }
+
+    /// Re-pack a heightmap saved before 20w17a, whose values can span across two longs, into the
+    /// layout that is used since 20w17a
+    pub(crate) fn unspan(&mut self, world: WorldHeight) {
+        let bits = world.heightmap_bits();
+        let values = unpack_spanning(&self.raw, bits, 256);
+        self.raw = LongArray::new(pack(&values, bits));
+    }
+
+    /// Convert the y value of the highest block into the number of blocks above the bottom of
+    /// the world that is stored in the heightmap
+    fn height_to_value(block_y: i32, world: WorldHeight) -> u64 {
+        (block_y + 1 - world.min_y).clamp(0, world.height as i32) as u64
+    }
+
+    const fn index(block_x: u32, block_z: u32) -> usize {
         assert!(block_x < 16);
         assert!(block_z < 16);
 
-        let index = (block_z * 16 + block_x) as usize;
+        (block_z * 16 + block_x) as usize
+    }
+
+    /// Get the height of a chunk using this heightmap at a positon (relative to the chunk), which is
+    /// the y value of the highest block in the column.
+    ///
+    /// `world` is the range of y values in the chunk, which decides how many bits are used for
+    /// each value and where the bottom of the world is, see [`ChunkNbt::world_height`].  Columns
+    /// without any blocks have a height of `world.min_y - 1`.
+    ///
+    /// Returns [`None`] if this heightmap does not have the length that `world` needs, i.e. when
+    /// `world` was derived from a chunk with missing sections.
+    pub fn get_height(&self, block_x: u32, block_z: u32, world: WorldHeight) -> Option<i32> {
+        let bits = world.heightmap_bits();
+        if self.raw.len() != 256usize.div_ceil((u64::BITS / bits) as usize) {
+            return None;
+        }
 
-        let num = self.raw[index / 7] as u64 >> ((index % 7) * 9) & (2u64.pow(9) - 1);
+        let num = get_item_in_packed_slice(&self.raw, Self::index(block_x, block_z), bits, 256);
 
-        num as i32 - 65
+        Some(num as i32 - 1 + world.min_y)
     }
 }
 
@@ -228,17 +548,246 @@ pub struct BlockStates {
     pub data: Option<LongArray>,
 }
 
+impl BlockStates {
+    /// The number of blocks in a section
+    pub(crate) const LEN: usize = 4096;
+    /// The minimum number of bits used for each index in `data`
+    pub(crate) const MIN_BITS: u32 = 4;
+
+    /// Block states for a section which is filled with air
+    fn air() -> Self {
+        Self {
+            palette: vec![BlockState::new(NamespacedKey::minecraft("air".into()))],
+            data: None,
+        }
+    }
+
+    /// Get the block state at an index into the section, where the index is `y * 256 + z * 16 +
+    /// x`
+    pub fn get(&self, index: usize) -> Option<&BlockState> {
+        let Some(data) = &self.data else {
+            return self.palette.first();
+        };
+
+        let bits = palette_bits(self.palette.len(), Self::MIN_BITS);
+        let block = get_item_in_packed_slice(data, index, bits, Self::LEN);
+
+        self.palette.get(block as usize)
+    }
+
+    /// Decode the palette index of every block in the section at once, in the same order as
+    /// [`BlockStates::get`]
+    pub fn palette_indices(&self) -> Vec<u64> {
+        match &self.data {
+            Some(data) => unpack(
+                data,
+                palette_bits(self.palette.len(), Self::MIN_BITS),
+                Self::LEN,
+            ),
+            None => vec![0; Self::LEN],
+        }
+    }
+
+    /// Get an iterator over all 4096 blocks in this section, in the order they are stored.
+    ///
+    /// The iterator yields `(x, y, z, state)` where `x`, `y`, and `z` are relative to the section
+    /// (`0..=15`).
+    ///
+    /// The packed data is only decoded once, rather than for every block.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32, u32, &BlockState)> {
+        self.palette_indices()
+            .into_iter()
+            .enumerate()
+            .filter_map(move |(i, block)| {
+                let i = i as u32;
+                let state = self.palette.get(block as usize)?;
+                Some((i % 16, i / 256, (i / 16) % 16, state))
+            })
+    }
+
+    /// Set the block state at an index into the section, where the index is `y * 256 + z * 16 +
+    /// x`
+    ///
+    /// The state is added to the palette if it is not already in it, and `data` is re-packed if
+    /// the number of bits needed for each index changes.  Palette entries which are no longer
+    /// used are _not_ removed.
+    pub fn set(&mut self, index: usize, state: BlockState) {
+        assert!(index < Self::LEN);
+
+        // The width of the data has to come from the palette, since several widths can use the
+        // same number of longs (i.e. 11 and 12 bits)
+        let current_bits = self
+            .data
+            .as_ref()
+            .map(|_| palette_bits(self.palette.len(), Self::MIN_BITS));
+
+        let palette_index = match self.palette.iter().position(|b| *b == state) {
+            Some(i) => i,
+            None => {
+                self.palette.push(state);
+                self.palette.len() - 1
+            }
+        };
+
+        // The whole section is this one state, so there is nothing to store
+        if self.palette.len() == 1 {
+            self.data = None;
+            return;
+        }
+
+        let bits = palette_bits(self.palette.len(), Self::MIN_BITS);
+
+        match &mut self.data {
+            Some(data) if current_bits == Some(bits) => {
+                set_item_in_packed_slice(data, index, bits, palette_index as u64);
+            }
+            data => {
+                let mut indices = match (data.as_ref(), current_bits) {
+                    (Some(d), Some(current_bits)) => unpack(d, current_bits, Self::LEN),
+                    _ => vec![0; Self::LEN],
+                };
+                indices[index] = palette_index as u64;
+
+                *data = Some(LongArray::new(pack(&indices, bits)));
+            }
+        }
+    }
+
+    /// Remove unused and duplicate entries from the palette, and re-pack `data` at the minimum
+    /// number of bits (with vanilla's minimum of 4 bits).  If only one block state is left, `data`
+    /// is removed.
+    pub fn normalize(&mut self) {
+        let bits = palette_bits(self.palette.len(), Self::MIN_BITS);
+
+        normalize_palette(
+            &mut self.palette,
+            &mut self.data,
+            bits,
+            Self::LEN,
+            Self::MIN_BITS,
+        );
+    }
+}
+
 /// Data which represents a block in a chunk
 ///
+/// Block states can be parsed from and formatted as the syntax used in commands, i.e.
+/// `minecraft:oak_stairs[facing=north,half=top]`:
+///
+/// ```
+/// # use mca_parser::nbt::BlockState;
+/// let state: BlockState = "oak_stairs[facing=north, half=top]".parse()?;
+/// assert_eq!(state.get("facing"), Some("north"));
+/// assert_eq!(state.to_string(), "minecraft:oak_stairs[facing=north,half=top]");
+/// # Ok::<(), mca_parser::error::Error>(())
+/// ```
+///
 /// - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+/// - See <https://minecraft.wiki/w/Block_states>
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct BlockState {
     /// Block [resource location](https://minecraft.wiki/w/Resource_location)
     #[serde(rename = "Name")]
     pub name: NamespacedKey,
-    /// Properties of the block state
-    #[serde(rename = "Properties")]
-    pub properties: Option<Value>,
+    /// Properties of the block state, which is empty if the block doesn't have any properties
+    #[serde(rename = "Properties", default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+impl BlockState {
+    /// Create a block state without any properties
+    pub fn new(name: impl Into<NamespacedKey>) -> Self {
+        Self {
+            name: name.into(),
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// Get the value of a property, if it is set
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(String::as_str)
+    }
+
+    /// Get the value of a boolean property (i.e. `waterlogged`), returns [`None`] if it is not
+    /// set or is not `true` or `false`
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get(name)?.parse().ok()
+    }
+
+    /// Get the value of an integer property (i.e. `age`), returns [`None`] if it is not set or is
+    /// not an integer
+    pub fn get_int(&self, name: &str) -> Option<i32> {
+        self.get(name)?.parse().ok()
+    }
+
+    /// Get the value of a property which is one of a set of values (i.e. `facing`), by parsing it
+    /// into `T`.  Returns [`None`] if it is not set or `T` fails to parse it.
+    pub fn get_enum<T>(&self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+    {
+        self.get(name)?.parse().ok()
+    }
+
+    /// Set the value of a property, returning the previous value if it was set
+    pub fn set(&mut self, name: impl Into<String>, value: impl ToString) -> Option<String> {
+        self.properties.insert(name.into(), value.to_string())
+    }
+}
+
+impl FromStr for BlockState {
+    type Err = Error;
+
+    /// Parse a block state from the syntax used in commands, i.e.
+    /// `minecraft:oak_stairs[facing=north,half=top]`.  Whitespace around names and values is
+    /// ignored, and the namespace defaults to `minecraft`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || Error::InvalidBlockState(s.into());
+
+        let (name, properties) = match s.split_once('[') {
+            Some((name, rest)) => (name, Some(rest.strip_suffix(']').ok_or_else(invalid)?)),
+            None => (s, None),
+        };
+
+        let name = name.trim();
+        if name.is_empty() || name.contains(']') {
+            return Err(invalid());
+        }
+
+        let mut state = BlockState::new(name);
+        for property in properties.into_iter().flat_map(|p| p.split(',')) {
+            if property.trim().is_empty() {
+                continue;
+            }
+
+            let (k, v) = property.split_once('=').ok_or_else(invalid)?;
+            let (k, v) = (k.trim(), v.trim());
+            if k.is_empty() || v.is_empty() {
+                return Err(invalid());
+            }
+
+            state.set(k, v);
+        }
+
+        Ok(state)
+    }
+}
+
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if !self.properties.is_empty() {
+            let properties: Vec<_> = self
+                .properties
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect();
+            write!(f, "[{}]", properties.join(","))?;
+        }
+
+        Ok(())
+    }
 }
 
 /// - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>
@@ -257,15 +806,101 @@ pub struct Biomes {
     pub data: Option<LongArray>,
 }
 
+impl Biomes {
+    /// The number of biome cells in a section
+    const LEN: usize = 64;
+
+    /// Get the biome at an index into the section's 4x4x4 biome cells, where the index is `y * 16
+    /// + z * 4 + x`
+    pub fn get(&self, index: usize) -> Option<&str> {
+        let data = match &self.data {
+            Some(data) if self.palette.len() > 1 => data,
+            _ => return self.palette.first().map(String::as_str),
+        };
+
+        let bits = palette_bits(self.palette.len(), 0);
+        let biome = get_item_in_packed_slice(data, index, bits, Self::LEN);
+
+        self.palette.get(biome as usize).map(String::as_str)
+    }
+
+    /// Get an iterator over all 64 biome cells in this section, in the order they are stored.
+    ///
+    /// The iterator yields `(x, y, z, biome)` where `x`, `y`, and `z` are the coordinates of the
+    /// _cell_ within the section (`0..=3`), so multiply them by 4 to get block coordinates.
+    ///
+    /// The packed data is only decoded once, rather than for every cell.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32, u32, &str)> {
+        let indices = match &self.data {
+            Some(data) if self.palette.len() > 1 => {
+                unpack(data, palette_bits(self.palette.len(), 0), Self::LEN)
+            }
+            _ => vec![0; Self::LEN],
+        };
+
+        indices
+            .into_iter()
+            .enumerate()
+            .filter_map(move |(i, biome)| {
+                let i = i as u32;
+                let biome = self.palette.get(biome as usize)?;
+                Some((i % 4, i / 16, (i / 4) % 4, biome.as_str()))
+            })
+    }
+
+    /// Set the biome at an index into the section's 4x4x4 biome cells, where the index is `y * 16
+    /// + z * 4 + x`
+    ///
+    /// The biome is added to the palette if it is not already in it, biomes which are no longer
+    /// used are removed from the palette, and `data` is re-packed at the minimum number of bits.
+    /// If the whole section ends up as one biome, `data` is removed.
+    pub fn set(&mut self, index: usize, biome: &str) {
+        assert!(index < Self::LEN);
+
+        let bits = palette_bits(self.palette.len(), 0);
+        let mut indices = match &self.data {
+            Some(data) if self.palette.len() > 1 => unpack(data, bits, Self::LEN),
+            _ => vec![0; Self::LEN],
+        };
+
+        indices[index] = match self.palette.iter().position(|b| b == biome) {
+            Some(i) => i,
+            None => {
+                self.palette.push(biome.into());
+                self.palette.len() - 1
+            }
+        } as u64;
+
+        // `normalize_palette` needs the data at its current width, so pack it before normalizing
+        let bits = palette_bits(self.palette.len(), 0);
+        let mut data = (bits > 0).then(|| LongArray::new(pack(&indices, bits)));
+
+        normalize_palette(&mut self.palette, &mut data, bits, Self::LEN, 0);
+        self.data = data;
+    }
+
+    /// Remove unused and duplicate entries from the palette, and re-pack `data` at the minimum
+    /// number of bits.  If only one biome is left, `data` is removed.
+    pub fn normalize(&mut self) {
+        let bits = palette_bits(self.palette.len(), 0);
+
+        normalize_palette(&mut self.palette, &mut self.data, bits, Self::LEN, 0);
+    }
+}
+
 /// - See <https://minecraft.wiki/w/Chunk_format#Tile_tick_format>
 #[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct TileTick {
     /// The ID of the block; used to activate the correct block update procedure.
+    ///
+    /// Tile ticks saved before 1.8 use the numeric id of the block, see [`TickId`].
     #[serde(rename = "i")]
-    pub id: String,
+    pub id: TickId,
     /// If multiple tile ticks are scheduled for the same tick, tile ticks with lower priority are
     /// processed first. If they also have the same priority, the order is unknown.
-    #[serde(rename = "p")]
+    ///
+    /// Tile ticks saved before 1.8 do not have a priority, so it defaults to 0.
+    #[serde(rename = "p", default)]
     pub priority: i32,
     /// The number of ticks until processing should occur. May be negative when processing is
     /// overdue.
@@ -279,6 +914,48 @@ pub struct TileTick {
     pub z: i32,
 }
 
+/// The id of the block or fluid that a [`TileTick`] is for
+#[derive(Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[serde(untagged)]
+pub enum TickId {
+    /// The name of the block or fluid, i.e. `minecraft:water`
+    Name(NamespacedKey),
+    /// The numeric id of the block, which was used before 1.8
+    Numeric(i32),
+}
+
+impl TickId {
+    /// Get the name of the block or fluid.  Numeric ids are mapped to the name of the block
+    /// since 1.13, see [`crate::legacy::blocks::block_state`].
+    pub fn name(&self) -> NamespacedKey {
+        match self {
+            Self::Name(name) => name.clone(),
+            Self::Numeric(id) => crate::legacy::blocks::block_state(*id as u16, 0).name,
+        }
+    }
+}
+
+impl From<&str> for TickId {
+    fn from(value: &str) -> Self {
+        Self::Name(value.into())
+    }
+}
+
+impl fmt::Display for TickId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The kind of a [`TileTick`], which decides which list in the chunk it is stored in
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TickKind {
+    /// A tick for a block, stored in [`ChunkNbt::block_ticks`]
+    Block,
+    /// A tick for a fluid, stored in [`ChunkNbt::fluid_ticks`]
+    Fluid,
+}
+
 /// The represents a section (or subchunk) from a chunk's NBT data stored in the region file
 ///
 /// - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>
@@ -291,4 +968,210 @@ pub struct ChunkSection {
     pub y: i8,
     /// Biomes used in this chunk
     pub biomes: Option<Biomes>,
+    /// 2048 bytes recording the amount of block-emitted light in each block, 4 bits per block.
+    ///
+    /// If this is missing, the section has no block light (or it has not been calculated).
+    #[serde(rename = "BlockLight")]
+    pub block_light: Option<ByteArray>,
+    /// 2048 bytes recording the amount of sunlight or moonlight hitting each block, 4 bits per
+    /// block.
+    ///
+    /// If this is missing, the section has no sky light (or it has not been calculated).
+    #[serde(rename = "SkyLight")]
+    pub sky_light: Option<ByteArray>,
+}
+
+impl ChunkSection {
+    /// Create a new section at section y-value `y` which is filled with air
+    pub fn new(y: i8) -> Self {
+        Self {
+            block_states: Some(BlockStates::air()),
+            y,
+            biomes: None,
+            block_light: None,
+            sky_light: None,
+        }
+    }
+
+    /// Get a block from this section using block_{x,y,z}, which are all relative to the section
+    ///
+    /// # Panics
+    ///
+    /// - If `x`, `y`, or `z` are not within `0..=15`
+    pub fn get_block(&self, block_x: u32, block_y: u32, block_z: u32) -> Option<&BlockState> {
+        self.block_states
+            .as_ref()?
+            .get(Self::block_index(block_x, block_y, block_z))
+    }
+
+    /// Set a block in this section using block_{x,y,z}, which are all relative to the section
+    ///
+    /// If this section has no block states, it is treated as being filled with air.  See
+    /// [`BlockStates::set`] for how the palette is maintained.
+    ///
+    /// # Panics
+    ///
+    /// - If `x`, `y`, or `z` are not within `0..=15`
+    pub fn set_block(&mut self, block_x: u32, block_y: u32, block_z: u32, state: BlockState) {
+        let index = Self::block_index(block_x, block_y, block_z);
+
+        self.block_states
+            .get_or_insert_with(BlockStates::air)
+            .set(index, state);
+    }
+
+    /// Get an iterator over the 4096 blocks in this section, see [`BlockStates::iter`].
+    ///
+    /// The iterator is empty if this section has no block states.
+    pub fn blocks(&self) -> impl Iterator<Item = (u32, u32, u32, &BlockState)> {
+        self.block_states.iter().flat_map(BlockStates::iter)
+    }
+
+    /// Find the blocks in this section whose state matches `predicate`, as block_{x,y,z} relative
+    /// to the section
+    ///
+    /// `predicate` is only called once for each entry in the palette, and the packed data is not
+    /// decoded at all if no entry matches.
+    pub fn find_blocks<F>(&self, mut predicate: F) -> Vec<(u32, u32, u32)>
+    where
+        F: FnMut(&BlockState) -> bool,
+    {
+        let Some(block_states) = &self.block_states else {
+            return Vec::new();
+        };
+
+        let matches: Vec<bool> = block_states.palette.iter().map(&mut predicate).collect();
+        if !matches.contains(&true) {
+            return Vec::new();
+        }
+
+        block_states
+            .palette_indices()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, block)| matches.get(*block as usize) == Some(&true))
+            .map(|(i, _)| {
+                let i = i as u32;
+                (i % 16, i / 256, (i / 16) % 16)
+            })
+            .collect()
+    }
+
+    /// Get the biome in this section using block_{x,y,z}, which are all relative to the section
+    ///
+    /// Biomes are stored in 4x4x4 cells, so every block within the same cell has the same biome.
+    ///
+    /// # Panics
+    ///
+    /// - If `x`, `y`, or `z` are not within `0..=15`
+    pub fn get_biome(&self, block_x: u32, block_y: u32, block_z: u32) -> Option<&str> {
+        self.biomes
+            .as_ref()?
+            .get(Self::biome_index(block_x, block_y, block_z))
+    }
+
+    /// Set the biome in this section using block_{x,y,z}, which are all relative to the section.
+    /// This sets the biome of the whole 4x4x4 cell which contains the block.
+    ///
+    /// If this section has no biomes, the whole section is set to `biome`.  See [`Biomes::set`]
+    /// for how the palette is maintained.
+    ///
+    /// # Panics
+    ///
+    /// - If `x`, `y`, or `z` are not within `0..=15`
+    pub fn set_biome(&mut self, block_x: u32, block_y: u32, block_z: u32, biome: &str) {
+        let index = Self::biome_index(block_x, block_y, block_z);
+
+        match &mut self.biomes {
+            Some(biomes) => biomes.set(index, biome),
+            None => {
+                self.biomes = Some(Biomes {
+                    palette: vec![biome.into()],
+                    data: None,
+                })
+            }
+        }
+    }
+
+    /// Get an iterator over the 64 biome cells in this section, see [`Biomes::iter`].
+    ///
+    /// The iterator is empty if this section has no biomes.
+    pub fn biome_cells(&self) -> impl Iterator<Item = (u32, u32, u32, &str)> {
+        self.biomes.iter().flat_map(Biomes::iter)
+    }
+
+    /// Get the block light level (`0..=15`) in this section using block_{x,y,z}, which are all
+    /// relative to the section
+    ///
+    /// If this section has no `BlockLight` array, the light level is `0`.
+    ///
+    /// # Panics
+    ///
+    /// - If `x`, `y`, or `z` are not within `0..=15`
+    pub fn get_block_light(&self, block_x: u32, block_y: u32, block_z: u32) -> u8 {
+        let index = Self::block_index(block_x, block_y, block_z);
+        self.block_light
+            .as_ref()
+            .map_or(0, |light| get_nibble(light, index))
+    }
+
+    /// Get the sky light level (`0..=15`) in this section using block_{x,y,z}, which are all
+    /// relative to the section
+    ///
+    /// If this section has no `SkyLight` array, the light level is `0`.
+    ///
+    /// # Panics
+    ///
+    /// - If `x`, `y`, or `z` are not within `0..=15`
+    pub fn get_sky_light(&self, block_x: u32, block_y: u32, block_z: u32) -> u8 {
+        let index = Self::block_index(block_x, block_y, block_z);
+        self.sky_light
+            .as_ref()
+            .map_or(0, |light| get_nibble(light, index))
+    }
+
+    /// Clean up the palettes of this section after edits (or data from third-party tools)
+    ///
+    /// Unused and duplicate palette entries are dropped, the indices are remapped, and the data is
+    /// re-packed at the minimum number of bits.  Sections which only contain a single block state
+    /// (or biome) have their data removed.
+    ///
+    /// See [`BlockStates::normalize`] and [`Biomes::normalize`]
+    pub fn normalize(&mut self) {
+        if let Some(block_states) = &mut self.block_states {
+            block_states.normalize();
+        }
+
+        if let Some(biomes) = &mut self.biomes {
+            biomes.normalize();
+        }
+    }
+
+    /// Convert block_{x,y,z} relative to the section into an index into the block states
+    const fn block_index(block_x: u32, block_y: u32, block_z: u32) -> usize {
+        assert!(block_x < 16);
+        assert!(block_y < 16);
+        assert!(block_z < 16);
+
+        (block_y * 16 * 16 + block_z * 16 + block_x) as usize
+    }
+
+    /// Convert block_{x,y,z} relative to the section into an index into the biome cells
+    const fn biome_index(block_x: u32, block_y: u32, block_z: u32) -> usize {
+        assert!(block_x < 16);
+        assert!(block_y < 16);
+        assert!(block_z < 16);
+
+        ((block_y / 4) * 4 * 4 + (block_z / 4) * 4 + block_x / 4) as usize
+    }
+}
+
+/// Get the 4-bit value at `index` in a nibble array, where even indices are stored in the low bits
+/// of each byte.  Arrays which are too short are treated as being zero.
+pub(crate) fn get_nibble(arr: &[i8], index: usize) -> u8 {
+    let Some(byte) = arr.get(index / 2) else {
+        return 0;
+    };
+
+    (*byte as u8 >> ((index % 2) * 4)) & 0xf
 }
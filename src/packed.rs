@@ -0,0 +1,241 @@
+//! Helpers for reading and writing the packed long arrays that chunk sections use to store indices
+//! into their palettes.
+//!
+//! Since 20w17a, indices are not packed across multiple longs, so if there is no more space in a
+//! long for the whole next index, it starts at the lowest bit of the next long instead.
+//!
+//! Before 20w17a, indices were packed continuously, so an index could start in one long and end
+//! in the next.  Slices in this layout are only read with [`unpack_spanning`], and are re-packed
+//! into the newer layout when a legacy chunk is converted (see [`crate::legacy`]).
+//!
+//! - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>
+
+use fastnbt::LongArray;
+
+/// The `DataVersion` of 20w17a, the first version which doesn't pack items across multiple longs
+pub(crate) const NO_SPANNING_DATA_VERSION: i32 = 2529;
+
+/// Check if packed slices saved with `data_version` have items which span across multiple longs
+pub(crate) fn is_spanning(data_version: i32) -> bool {
+    data_version < NO_SPANNING_DATA_VERSION
+}
+
+/// Get the number of bits used for each index into a palette of `len` entries, with a minimum of
+/// `min` bits
+pub(crate) fn palette_bits(len: usize, min: u32) -> u32 {
+    let bits = usize::BITS - len.saturating_sub(1).leading_zeros();
+    std::cmp::max(bits, min)
+}
+
+/// Get the item at `index` out of a packed slice of `len` items which are each `bits` wide
+pub(crate) fn get_item_in_packed_slice(slice: &[i64], index: usize, bits: u32, len: usize) -> u64 {
+    let nums_per_u64 = u64::BITS / bits;
+    assert_eq!(slice.len(), len.div_ceil(nums_per_u64 as usize));
+    let index_in_num = index as u32 % nums_per_u64;
+    let shifted_num = slice[index / nums_per_u64 as usize] as u64 >> (bits * index_in_num);
+    shifted_num & (2u64.pow(bits) - 1)
+}
+
+/// Set the item at `index` in a packed slice where each item is `bits` wide
+///
+/// # Panics
+///
+/// - If `value` does not fit in `bits`
+pub(crate) fn set_item_in_packed_slice(slice: &mut [i64], index: usize, bits: u32, value: u64) {
+    let mask = 2u64.pow(bits) - 1;
+    assert!(value <= mask);
+
+    let nums_per_u64 = u64::BITS / bits;
+    let shift = bits * (index as u32 % nums_per_u64);
+    let num = &mut slice[index / nums_per_u64 as usize];
+
+    *num = ((*num as u64 & !(mask << shift)) | (value << shift)) as i64;
+}
+
+/// Unpack all `len` items from a packed slice where each item is `bits` wide
+pub(crate) fn unpack(slice: &[i64], bits: u32, len: usize) -> Vec<u64> {
+    (0..len)
+        .map(|i| get_item_in_packed_slice(slice, i, bits, len))
+        .collect()
+}
+
+/// Unpack all `len` items from a packed slice saved before 20w17a, where each item is `bits` wide
+/// and items can span across two longs
+///
+/// Items which would be past the end of `slice` are read as 0.
+pub(crate) fn unpack_spanning(slice: &[i64], bits: u32, len: usize) -> Vec<u64> {
+    let mask = 2u64.pow(bits) - 1;
+
+    (0..len)
+        .map(|i| {
+            let start = i * bits as usize;
+            let (index, offset) = (start / 64, (start % 64) as u32);
+
+            let Some(num) = slice.get(index) else {
+                return 0;
+            };
+            let mut item = *num as u64 >> offset;
+            if offset + bits > u64::BITS {
+                if let Some(next) = slice.get(index + 1) {
+                    item |= (*next as u64) << (u64::BITS - offset);
+                }
+            }
+
+            item & mask
+        })
+        .collect()
+}
+
+/// Pack `items` into a new vec of longs where each item is `bits` wide
+pub(crate) fn pack(items: &[u64], bits: u32) -> Vec<i64> {
+    let nums_per_u64 = (u64::BITS / bits) as usize;
+    let mut packed = vec![0; items.len().div_ceil(nums_per_u64)];
+
+    for (i, item) in items.iter().enumerate() {
+        set_item_in_packed_slice(&mut packed, i, bits, *item);
+    }
+
+    packed
+}
+
+/// Remove unused and duplicate entries from `palette`, remap the indices in `data` to match, and
+/// re-pack `data` at the minimum width for the new palette.
+///
+/// `bits` is the current width of the `len` items in `data`, and `min_bits` is the minimum width
+/// that `data` can be re-packed at.  If only one entry is left in the palette, `data` is removed.
+pub(crate) fn normalize_palette<T>(
+    palette: &mut Vec<T>,
+    data: &mut Option<LongArray>,
+    bits: u32,
+    len: usize,
+    min_bits: u32,
+) where
+    T: PartialEq,
+{
+    if palette.is_empty() {
+        return;
+    }
+
+    let mut indices = match data {
+        Some(d) if palette.len() > 1 => unpack(d, bits, len),
+        _ => vec![0; len],
+    };
+
+    // Indices which point outside of the palette are treated as the first entry
+    let mut used = vec![false; palette.len()];
+    for index in &mut indices {
+        if *index as usize >= palette.len() {
+            *index = 0;
+        }
+        used[*index as usize] = true;
+    }
+
+    let mut remap = vec![0; palette.len()];
+    let mut new_palette: Vec<T> = Vec::with_capacity(palette.len());
+    for (i, entry) in palette.drain(..).enumerate() {
+        if !used[i] {
+            continue;
+        }
+
+        remap[i] = match new_palette.iter().position(|e| *e == entry) {
+            Some(existing) => existing,
+            None => {
+                new_palette.push(entry);
+                new_palette.len() - 1
+            }
+        } as u64;
+    }
+    *palette = new_palette;
+
+    if palette.len() == 1 {
+        *data = None;
+        return;
+    }
+
+    for index in &mut indices {
+        *index = remap[*index as usize];
+    }
+
+    *data = Some(LongArray::new(pack(
+        &indices,
+        palette_bits(palette.len(), min_bits),
+    )));
+}
+
+#[test]
+fn test_get_item_in_packed_slice() {
+    let slice = &[0; 128];
+    assert_eq!(get_item_in_packed_slice(slice, 15, 2, 4096), 0);
+    let slice = &[0; 456];
+    assert_eq!(get_item_in_packed_slice(slice, 15, 7, 4096), 0);
+    let slice = &[0b10_01_00];
+    assert_eq!(get_item_in_packed_slice(slice, 2, 2, 3), 2);
+}
+
+#[test]
+fn test_palette_bits() {
+    assert_eq!(palette_bits(1, 0), 0);
+    assert_eq!(palette_bits(2, 0), 1);
+    assert_eq!(palette_bits(5, 0), 3);
+    assert_eq!(palette_bits(2, 4), 4);
+    assert_eq!(palette_bits(16, 4), 4);
+    assert_eq!(palette_bits(17, 4), 5);
+}
+
+#[test]
+fn test_unpack_spanning() {
+    // 5 bit items: the 13th item starts at bit 60, so it spans both longs
+    let items: Vec<u64> = (0..20).map(|i| (i * 7) % 32).collect();
+    let mut packed = [0i64; 2];
+    for (i, item) in items.iter().enumerate() {
+        let start = i * 5;
+        packed[start / 64] |= (*item << (start % 64)) as i64;
+        if start % 64 + 5 > 64 {
+            packed[start / 64 + 1] |= (*item >> (64 - start % 64)) as i64;
+        }
+    }
+
+    assert_eq!(unpack_spanning(&packed, 5, 20), items);
+    assert!(is_spanning(2230));
+    assert!(!is_spanning(2586));
+}
+
+#[test]
+fn test_pack_roundtrip() {
+    let items: Vec<u64> = (0..4096).map(|i| i % 20).collect();
+    let packed = pack(&items, 5);
+
+    // 12 items fit in each long at 5 bits per item
+    assert_eq!(packed.len(), 342);
+    assert_eq!(unpack(&packed, 5, 4096), items);
+
+    let mut packed = packed;
+    set_item_in_packed_slice(&mut packed, 13, 5, 31);
+    assert_eq!(get_item_in_packed_slice(&packed, 13, 5, 4096), 31);
+    assert_eq!(get_item_in_packed_slice(&packed, 12, 5, 4096), 12);
+    assert_eq!(get_item_in_packed_slice(&packed, 14, 5, 4096), 14);
+}
+
+#[test]
+fn test_normalize_palette() {
+    let mut palette = vec!["a", "b", "c", "b", "d"];
+    // Only uses "b" (twice in the palette) and "d"
+    let items: Vec<u64> = (0..64).map(|i| [1, 3, 4][i % 3]).collect();
+    let mut data = Some(LongArray::new(pack(&items, 3)));
+
+    normalize_palette(&mut palette, &mut data, 3, 64, 0);
+
+    assert_eq!(palette, ["b", "d"]);
+    let data = data.unwrap();
+    assert_eq!(data.len(), 1);
+    for (i, item) in unpack(&data, 1, 64).into_iter().enumerate() {
+        assert_eq!(item, [0, 0, 1][i % 3]);
+    }
+
+    let mut palette = vec!["a", "b"];
+    let mut data = Some(LongArray::new(pack(&[1; 4096], 4)));
+    normalize_palette(&mut palette, &mut data, 4, 4096, 4);
+
+    assert_eq!(palette, ["b"]);
+    assert_eq!(data, None);
+}
@@ -0,0 +1,277 @@
+//! Counting blocks by type, for reports such as the distribution of ores by y level, and chunks
+//! by generation status, see [`StatusReport`].
+//!
+//! Blocks are counted from the palette of each section, so sections which are entirely one block
+//! (i.e. all air or all stone) are counted without decoding any data.
+//!
+//! ```no_run
+//! # use mca_parser::{nbt::NamespacedKey, stats::BlockStats, Dimension};
+//! let dim = Dimension::from_path("world/region")?;
+//!
+//! let mut stats = BlockStats::new();
+//! stats.add_dimension(&dim);
+//!
+//! let diamonds = NamespacedKey::from("deepslate_diamond_ore");
+//! for (y, counts) in stats.levels() {
+//!     println!("{}: {}", y, counts.get(&diamonds).unwrap_or(&0));
+//! }
+//! println!("{} chunks failed to parse", stats.errors());
+//! # Ok::<(), mca_parser::error::Error>(())
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    nbt::{ChunkSection, ChunkStatus, NamespacedKey},
+    Dimension, ParsedChunk, Region, RegionParser, Result,
+};
+
+/// Counts of blocks, both in total and for each y level
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockStats {
+    totals: HashMap<NamespacedKey, u64>,
+    levels: BTreeMap<i32, HashMap<NamespacedKey, u64>>,
+    errors: u64,
+}
+
+impl BlockStats {
+    /// Create an empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the blocks in a section.  Sections without block states are skipped.
+    pub fn add_section(&mut self, section: &ChunkSection) {
+        let Some(block_states) = &section.block_states else {
+            return;
+        };
+        let base_y = section.y as i32 * 16;
+
+        // Sections which are one block have no data, so every block is the first in the palette
+        if block_states.data.is_none() || block_states.palette.len() == 1 {
+            if let Some(state) = block_states.palette.first() {
+                for y in 0..16 {
+                    self.add(base_y + y, &state.name, 16 * 16);
+                }
+            }
+            return;
+        }
+
+        // Count the palette indices first, so that the keys are only hashed once per level
+        let mut counts = vec![[0u64; 16]; block_states.palette.len()];
+        for (i, index) in block_states.palette_indices().into_iter().enumerate() {
+            if let Some(c) = counts.get_mut(index as usize) {
+                c[i / 256] += 1;
+            }
+        }
+
+        for (state, counts) in block_states.palette.iter().zip(counts) {
+            for (y, count) in counts.into_iter().enumerate() {
+                if count > 0 {
+                    self.add(base_y + y as i32, &state.name, count);
+                }
+            }
+        }
+    }
+
+    /// Count the blocks in every section of a chunk
+    pub fn add_chunk(&mut self, chunk: &ParsedChunk) {
+        for section in &chunk.sections {
+            self.add_section(section);
+        }
+    }
+
+    /// Count the blocks in every chunk of a region
+    ///
+    /// Chunks which fail to parse are skipped and counted in [`BlockStats::errors`].
+    pub fn add_region(&mut self, region: &Region) {
+        for i in 0..32 * 32 {
+            let chunk = region
+                .get_chunk(i % 32, i / 32)
+                .and_then(|chunk| chunk.map(|c| c.parse()).transpose());
+            self.add_result(chunk);
+        }
+    }
+
+    /// Count the blocks in every chunk of a dimension, see [`Dimension::parse_chunks`]
+    ///
+    /// Regions and chunks which fail to parse are skipped and counted in
+    /// [`BlockStats::errors`].
+    pub fn add_dimension<R>(&mut self, dimension: &Dimension<R>)
+    where
+        R: RegionParser,
+    {
+        for chunk in dimension.parse_chunks() {
+            self.add_result(chunk.map(Some));
+        }
+    }
+
+    /// Count the blocks in a chunk if it was parsed, or count the error if it failed to parse
+    fn add_result(&mut self, chunk: Result<Option<ParsedChunk>>) {
+        match chunk {
+            Ok(Some(chunk)) => self.add_chunk(&chunk),
+            Ok(None) => {}
+            Err(_) => self.errors += 1,
+        }
+    }
+
+    /// Add the counts from another collector to this one, i.e. to combine the counts of chunks
+    /// which were counted on different threads
+    pub fn merge(&mut self, other: &BlockStats) {
+        for (&y, counts) in &other.levels {
+            for (key, &count) in counts {
+                self.add(y, key, count);
+            }
+        }
+        self.errors += other.errors;
+    }
+
+    /// Get the total number of blocks of each type
+    pub fn totals(&self) -> &HashMap<NamespacedKey, u64> {
+        &self.totals
+    }
+
+    /// Get the total number of blocks of type `key`
+    pub fn total(&self, key: &NamespacedKey) -> u64 {
+        self.totals.get(key).copied().unwrap_or(0)
+    }
+
+    /// Get the number of regions and chunks which failed to parse, and were skipped, while adding
+    /// a region or dimension
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Get the total number of blocks that have been counted, of any type
+    pub fn total_blocks(&self) -> u64 {
+        self.totals.values().sum()
+    }
+
+    /// Get the number of blocks of each type at each y level, from the lowest level up.  Levels
+    /// without any counted blocks are skipped.
+    pub fn levels(&self) -> impl Iterator<Item = (i32, &HashMap<NamespacedKey, u64>)> {
+        self.levels.iter().map(|(y, counts)| (*y, counts))
+    }
+
+    /// Get the number of blocks of type `key` at y level `y`
+    pub fn count_at(&self, y: i32, key: &NamespacedKey) -> u64 {
+        self.levels
+            .get(&y)
+            .and_then(|counts| counts.get(key))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get the fraction (`0.0..=1.0`) of the counted blocks at y level `y` that are of type
+    /// `key`, i.e. the air ratio of a level.  Returns [`None`] if no blocks were counted at `y`.
+    pub fn ratio_at(&self, y: i32, key: &NamespacedKey) -> Option<f64> {
+        let total: u64 = self.levels.get(&y)?.values().sum();
+        Some(self.count_at(y, key) as f64 / total as f64)
+    }
+
+    /// Add `count` blocks of type `key` at y level `y`
+    fn add(&mut self, y: i32, key: &NamespacedKey, count: u64) {
+        *self.totals.entry(key.clone()).or_default() += count;
+        *self
+            .levels
+            .entry(y)
+            .or_default()
+            .entry(key.clone())
+            .or_default() += count;
+    }
+}
+
+/// Counts of chunks at each generation status, i.e. to find how many chunks are still
+/// proto-chunks after pre-generating a world
+///
+/// ```no_run
+/// # use mca_parser::{nbt::ChunkStatus, stats::StatusReport, Dimension};
+/// let dim = Dimension::from_path("world/region")?;
+/// let report = StatusReport::from_dimension(&dim);
+///
+/// for (status, count) in report.counts() {
+///     println!("{}: {}", status, count);
+/// }
+/// println!("{} of {} chunks are full", report.count(ChunkStatus::Full), report.total());
+/// println!("{} chunks failed to parse", report.errors());
+/// # Ok::<(), mca_parser::error::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatusReport {
+    counts: BTreeMap<ChunkStatus, u64>,
+    unknown: HashMap<NamespacedKey, u64>,
+    errors: u64,
+}
+
+impl StatusReport {
+    /// Create an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the status of every chunk in a dimension, see [`Dimension::parse_chunks`]
+    ///
+    /// Regions and chunks which fail to parse are skipped and counted in
+    /// [`StatusReport::errors`].
+    pub fn from_dimension<R>(dimension: &Dimension<R>) -> Self
+    where
+        R: RegionParser,
+    {
+        let mut report = Self::new();
+        for chunk in dimension.parse_chunks() {
+            match chunk {
+                Ok(chunk) => report.add_chunk(&chunk),
+                Err(_) => report.errors += 1,
+            }
+        }
+
+        report
+    }
+
+    /// Count the status of a chunk
+    pub fn add_chunk(&mut self, chunk: &ParsedChunk) {
+        match chunk.chunk_status() {
+            Some(status) => *self.counts.entry(status).or_default() += 1,
+            None => *self.unknown.entry(chunk.status.clone()).or_default() += 1,
+        }
+    }
+
+    /// Get the number of chunks at each status which at least one chunk is at, in the order that
+    /// the statuses are run
+    pub fn counts(&self) -> impl Iterator<Item = (ChunkStatus, u64)> + '_ {
+        self.counts.iter().map(|(status, count)| (*status, *count))
+    }
+
+    /// Get the number of chunks at `status`
+    pub fn count(&self, status: ChunkStatus) -> u64 {
+        self.counts.get(&status).copied().unwrap_or(0)
+    }
+
+    /// Get the number of chunks which have reached `status`, including those which are past it
+    pub fn at_least(&self, status: ChunkStatus) -> u64 {
+        self.counts.range(status..).map(|(_, count)| count).sum()
+    }
+
+    /// Get the number of chunks with each status which is not known, see
+    /// [`ChunkStatus::from_key`]
+    pub fn unknown(&self) -> &HashMap<NamespacedKey, u64> {
+        &self.unknown
+    }
+
+    /// Get the number of proto-chunks, which have not finished generating.  Chunks with an
+    /// unknown status are counted as proto-chunks.
+    pub fn proto_chunks(&self) -> u64 {
+        self.total() - self.count(ChunkStatus::Full)
+    }
+
+    /// Get the number of regions and chunks which failed to parse, and were skipped, while
+    /// creating this report.  These are not included in any of the other counts.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Get the total number of chunks that have been counted
+    pub fn total(&self) -> u64 {
+        self.counts.values().chain(self.unknown.values()).sum()
+    }
+}
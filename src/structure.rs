@@ -0,0 +1,217 @@
+//! Typed representations of the structure data that is stored in chunks.
+//!
+//! Each structure is stored in two places: the chunk that the structure starts in has a
+//! [`StructureStart`] with the pieces of the structure, and every chunk that the structure
+//! overlaps has a reference to the chunk where the structure starts.
+//!
+//! Structure starts which could not be parsed (such as those from mods with an unusual layout)
+//! are kept in [`Structures::unknown_starts`], so that they never stop the rest of the chunk
+//! from being parsed.
+//!
+//! - See <https://minecraft.wiki/w/Chunk_format#NBT_structure>
+//! - See <https://minecraft.wiki/w/Structure_format>
+
+use std::collections::HashMap;
+
+use fastnbt::{IntArray, LongArray, Value};
+use serde::Deserialize;
+
+/// The structure data of a chunk
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Structures {
+    /// The structures which start in this chunk, keyed by the structure id (i.e.
+    /// `minecraft:village_plains`).  Before 21w43a, the keys were the names of the structures
+    /// (i.e. `Village`).
+    ///
+    /// Structures which could not generate in this chunk are still saved, with an id of
+    /// `INVALID`, see [`StructureStart::is_valid`].
+    pub starts: HashMap<String, StructureStart>,
+    /// The structure starts which could not be parsed as a [`StructureStart`], keyed by the
+    /// structure id, with their nbt kept as it is
+    pub unknown_starts: HashMap<String, Value>,
+    /// The chunks that structures which overlap this chunk start in, keyed by the structure id.
+    /// Use [`Structures::references`] to unpack the positions.  References which are not a long
+    /// array are skipped.
+    pub references: HashMap<String, LongArray>,
+}
+
+impl<'de> Deserialize<'de> for Structures {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        let compound = |keys: &[&str]| match &value {
+            Value::Compound(c) => keys.iter().find_map(|k| match c.get(*k) {
+                Some(Value::Compound(c)) => Some(c),
+                _ => None,
+            }),
+            _ => None,
+        };
+
+        let mut structures = Self::default();
+        for (key, start) in compound(&["starts", "Starts"]).into_iter().flatten() {
+            match fastnbt::from_value(start) {
+                Ok(start) => {
+                    structures.starts.insert(key.clone(), start);
+                }
+                Err(_) => {
+                    structures.unknown_starts.insert(key.clone(), start.clone());
+                }
+            }
+        }
+        for (key, refs) in compound(&["References"]).into_iter().flatten() {
+            if let Value::LongArray(refs) = refs {
+                structures.references.insert(key.clone(), refs.clone());
+            }
+        }
+
+        Ok(structures)
+    }
+}
+
+impl Structures {
+    /// Get the starts of the structures in this chunk which were generated, skipping those with
+    /// an id of `INVALID`
+    pub fn valid_starts(&self) -> impl Iterator<Item = &StructureStart> {
+        self.starts.values().filter(|s| s.is_valid())
+    }
+
+    /// Get the absolute chunk positions (x, z) that the structures with the id `key` which
+    /// overlap this chunk start in
+    pub fn references(&self, key: &str) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.references
+            .get(key)
+            .into_iter()
+            .flat_map(|refs| refs.iter())
+            // Positions are packed with x in the lower 32 bits and z in the upper 32 bits
+            .map(|pos| (*pos as i32, (*pos >> 32) as i32))
+    }
+}
+
+/// The start of a structure, which holds all of the pieces of that structure
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct StructureStart {
+    /// The id of the structure (i.e. `minecraft:village_plains`), or `INVALID` if the structure
+    /// could not generate.  This is empty if the start has no id.
+    #[serde(default)]
+    pub id: String,
+    /// The x position of the chunk that this structure starts in
+    #[serde(rename = "ChunkX")]
+    pub chunk_x: Option<i32>,
+    /// The z position of the chunk that this structure starts in
+    #[serde(rename = "ChunkZ")]
+    pub chunk_z: Option<i32>,
+    /// The bounding box of the whole structure, which is only saved before 1.18.  Use
+    /// [`StructureStart::bounding_box`] to get the bounding box for any version.
+    #[serde(rename = "BB", default)]
+    pub bounding_box: Option<BoundingBox>,
+    /// The number of times that this structure has been referenced by a structure locator (such
+    /// as an explorer map or eye of ender)
+    pub references: Option<i32>,
+    /// The pieces that this structure is made up of
+    #[serde(rename = "Children", default)]
+    pub pieces: Vec<StructurePiece>,
+}
+
+impl StructureStart {
+    /// Check if this structure was generated, structures which could not generate have an id of
+    /// `INVALID`
+    pub fn is_valid(&self) -> bool {
+        self.id != "INVALID"
+    }
+
+    /// Get the bounding box of the whole structure, from the saved bounding box if present,
+    /// otherwise from the union of the bounding boxes of its pieces.
+    ///
+    /// Returns [`None`] if there is no saved bounding box and no pieces.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.bounding_box.or_else(|| {
+            self.pieces
+                .iter()
+                .filter_map(|p| p.bounding_box)
+                .reduce(BoundingBox::union)
+        })
+    }
+}
+
+/// A piece of a structure
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct StructurePiece {
+    /// The id of the type of piece (i.e. `minecraft:jigsaw`).  This is empty if the piece has no
+    /// id.
+    #[serde(default)]
+    pub id: String,
+    /// The bounding box of this piece, if it has one
+    #[serde(rename = "BB", default)]
+    pub bounding_box: Option<BoundingBox>,
+    /// The depth of this piece in the structure's generation
+    #[serde(rename = "GD")]
+    pub generation_depth: Option<i32>,
+    /// The orientation of this piece
+    #[serde(rename = "O")]
+    pub orientation: Option<i32>,
+}
+
+/// An axis-aligned box of blocks, where both corners are inclusive.  Stored in nbt as an int
+/// array of `[min_x, min_y, min_z, max_x, max_y, max_z]`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct BoundingBox {
+    /// The lowest x value in the box
+    pub min_x: i32,
+    /// The lowest y value in the box
+    pub min_y: i32,
+    /// The lowest z value in the box
+    pub min_z: i32,
+    /// The highest x value in the box
+    pub max_x: i32,
+    /// The highest y value in the box
+    pub max_y: i32,
+    /// The highest z value in the box
+    pub max_z: i32,
+}
+
+impl BoundingBox {
+    /// Get the smallest bounding box which contains both `self` and `other`
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            min_z: self.min_z.min(other.min_z),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+            max_z: self.max_z.max(other.max_z),
+        }
+    }
+
+    /// Check if a block is inside of this bounding box
+    pub fn contains(&self, x: i32, y: i32, z: i32) -> bool {
+        (self.min_x..=self.max_x).contains(&x)
+            && (self.min_y..=self.max_y).contains(&y)
+            && (self.min_z..=self.max_z).contains(&z)
+    }
+}
+
+impl<'de> Deserialize<'de> for BoundingBox {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let arr = IntArray::deserialize(deserializer)?;
+        let [min_x, min_y, min_z, max_x, max_y, max_z] = arr[..] else {
+            return Err(serde::de::Error::invalid_length(
+                arr.len(),
+                &"an int array of length 6",
+            ));
+        };
+
+        Ok(Self {
+            min_x,
+            min_y,
+            min_z,
+            max_x,
+            max_y,
+            max_z,
+        })
+    }
+}
@@ -222,6 +222,10 @@ fn test_region_file() {
         assert_eq!(rf.position(), Some((10, -100)));
         let rf = RegionFile::new("./test/r.20.10.mca");
         assert_eq!(rf.position(), Some((20, 10)));
+        let rf = RegionFile::new("./test/r.-3.4.mcr");
+        assert_eq!(rf.position(), Some((-3, 4)));
+        let rf = RegionFile::new("./test/r.-3.4.dat");
+        assert_eq!(rf.position(), None);
     }
 
     let region = rf.parse().unwrap();
@@ -271,10 +275,11 @@ fn test_heightmaps() {
     let mb = &chunk.height_maps.motion_blocking.as_ref().unwrap();
     // for x in 0..16 {
     //     for z in 0..16 {
-    //         eprintln!("{:?} = {}", (x, z), mb.get_height(x, z));
+    //         eprintln!("{:?} = {}", (x, z), mb.get_height(x, z, chunk.world_height()));
     //     }
     // }
-    dbg!(mb.get_height(0, 0));
+    assert_eq!(chunk.world_height(), nbt::WorldHeight::OVERWORLD);
+    assert!(mb.get_height(0, 0, chunk.world_height()).is_some());
 }
 
 #[test]
@@ -284,13 +289,1531 @@ fn test_block_in_chunk() {
     // mostly checking to confirm it doesn't crash
     assert_eq!(
         *chunk.get_block(4, 84, 10).unwrap(),
-        nbt::BlockState {
-            name: nbt::NamespacedKey::minecraft("grass_block".into()),
-            properties: Some(fastnbt::nbt!({
-                "snowy": "false",
-            })),
+        "minecraft:grass_block[snowy=false]"
+            .parse::<nbt::BlockState>()
+            .unwrap()
+    );
+
+    // Sections that are entirely one block have no data, but still have that block
+    assert_eq!(
+        chunk.get_block(13, 200, 15).unwrap().name,
+        nbt::NamespacedKey::minecraft("air".into())
+    );
+}
+
+#[test]
+fn test_region_boxed() {
+    let boxed = REGION.boxed();
+    assert_eq!(*boxed, *REGION);
+    assert_eq!(
+        std::mem::size_of_val(boxed.as_ref()),
+        std::mem::size_of_val(REGION)
+    );
+}
+
+#[test]
+fn test_dimension_get_block() {
+    let mut dim = Dimension::from_iter(None, [RegionFile::new("./test/r.0.0.mca")].into_iter());
+    let chunk = REGION.get_chunk(0, 0).unwrap().unwrap().parse().unwrap();
+
+    assert_eq!(
+        dim.get_block(4, 84, 10).unwrap(),
+        chunk.get_block(4, 84, 10)
+    );
+    assert_eq!(
+        dim.get_biome(4, 84, 10).unwrap(),
+        chunk.get_biome(4, 84, 10)
+    );
+
+    // Negative coordinates are in region (-1, -1), which does not exist
+    assert_eq!(dim.get_block(-1, 84, -1).unwrap(), None);
+}
+
+fn block(name: &str) -> nbt::BlockState {
+    nbt::BlockState::new(name)
+}
+
+#[test]
+fn test_block_state_string() {
+    let mut state: nbt::BlockState = " oak_stairs [ half = top, facing=north, waterlogged=false ]"
+        .parse()
+        .unwrap();
+    assert_eq!(state.name, "minecraft:oak_stairs".into());
+    assert_eq!(state.get("facing"), Some("north"));
+    assert_eq!(state.get_bool("waterlogged"), Some(false));
+    assert_eq!(state.get_bool("facing"), None);
+    assert_eq!(state.get_int("half"), None);
+    assert_eq!(state.get("shape"), None);
+    assert_eq!(
+        state.to_string(),
+        "minecraft:oak_stairs[facing=north,half=top,waterlogged=false]"
+    );
+
+    assert_eq!(state.set("waterlogged", true), Some("false".into()));
+    assert_eq!(state.get_bool("waterlogged"), Some(true));
+
+    let farmland: nbt::BlockState = "farmland[moisture=7]".parse().unwrap();
+    assert_eq!(farmland.get_int("moisture"), Some(7));
+    assert_eq!(farmland.get_enum::<u8>("moisture"), Some(7));
+    assert_eq!(farmland.get_enum::<bool>("moisture"), None);
+
+    // Formatting and parsing again gives the same state
+    let modded: nbt::BlockState = "mod:thing[]".parse().unwrap();
+    assert_eq!(modded, block("mod:thing"));
+    assert_eq!(modded.to_string(), "mod:thing");
+    assert_eq!(state.to_string().parse::<nbt::BlockState>().unwrap(), state);
+
+    for invalid in [
+        "",
+        "[a=b]",
+        "stone[facing",
+        "stone[a]",
+        "stone[=b]",
+        "stone[a=]",
+    ] {
+        assert!(
+            matches!(
+                invalid.parse::<nbt::BlockState>(),
+                Err(error::Error::InvalidBlockState(s)) if s == invalid
+            ),
+            "{invalid:?}"
+        );
+    }
+}
+
+#[test]
+fn test_section_set_block() {
+    let mut section = nbt::ChunkSection::new(0);
+    assert_eq!(section.get_block(0, 0, 0), Some(&block("air")));
+
+    // Setting the only block in the palette should not allocate data
+    section.set_block(0, 0, 0, block("air"));
+    assert_eq!(section.block_states.as_ref().unwrap().data, None);
+
+    // 16 entries fit in the minimum of 4 bits, and the 17th needs 5 bits
+    for i in 0..17 {
+        section.set_block(i % 16, i / 16, 3, block(&format!("block_{i}")));
+    }
+
+    let bs = section.block_states.as_ref().unwrap();
+    assert_eq!(bs.palette.len(), 18);
+    assert_eq!(bs.data.as_ref().unwrap().len(), 342);
+
+    for i in 0..17 {
+        assert_eq!(
+            section.get_block(i % 16, i / 16, 3),
+            Some(&block(&format!("block_{i}")))
+        );
+    }
+    assert_eq!(section.get_block(15, 15, 15), Some(&block("air")));
+}
+
+#[test]
+fn test_section_set_block_wide_palette() {
+    // 11 and 12 bits both use 820 longs, so the width has to come from the palette rather than
+    // the length of the data
+    let mut section = nbt::ChunkSection::new(0);
+    for i in 0..2100 {
+        section.set_block(i % 16, i / 256, (i / 16) % 16, block(&format!("block_{i}")));
+    }
+
+    let bs = section.block_states.as_ref().unwrap();
+    assert_eq!(bs.palette.len(), 2101);
+    assert_eq!(bs.data.as_ref().unwrap().len(), 820);
+    for i in 0..2100 {
+        assert_eq!(
+            section.get_block(i % 16, i / 256, (i / 16) % 16),
+            Some(&block(&format!("block_{i}")))
+        );
+    }
+    assert_eq!(section.get_block(15, 15, 15), Some(&block("air")));
+
+    section.normalize();
+    assert_eq!(section.get_block(4, 8, 2), Some(&block("block_2084")));
+    assert_eq!(section.get_block(15, 15, 15), Some(&block("air")));
+}
+
+#[test]
+fn test_blocks_iter() {
+    let mut chunk = empty_chunk(3, -2);
+    chunk.set_block(1, 2, 3, block("stone"));
+    chunk.set_block(15, 31, 0, block("dirt"));
+    chunk.set_block(0, 17, 0, block("stone"));
+
+    // Sections without block states have no blocks
+    chunk.sections.push(nbt::ChunkSection {
+        block_states: None,
+        ..nbt::ChunkSection::new(2)
+    });
+
+    let section = &chunk.sections[0];
+    assert_eq!(section.blocks().count(), 4096);
+    assert_eq!(
+        section.blocks().nth(1 + 3 * 16 + 2 * 256),
+        Some((1, 2, 3, &block("stone")))
+    );
+    for (x, y, z, state) in section.blocks() {
+        assert_eq!(section.get_block(x, y, z), Some(state));
+    }
+
+    let blocks: Vec<_> = chunk.blocks().collect();
+    assert_eq!(blocks.len(), 2 * 4096);
+    assert_eq!(blocks[4096], (0, 16, 0, &block("air")));
+    assert_eq!(blocks[8191], (15, 31, 15, &block("air")));
+    for (x, y, z, state) in blocks {
+        assert_eq!(chunk.get_block(x, y, z), Some(state));
+    }
+
+    let not_air: Vec<_> = chunk
+        .blocks()
+        .filter(|(.., state)| state.name != "air".into())
+        .map(|(x, y, z, state)| (x, y, z, state.name.key.as_str()))
+        .collect();
+    assert_eq!(
+        not_air,
+        [(1, 2, 3, "stone"), (0, 17, 0, "stone"), (15, 31, 0, "dirt")]
+    );
+}
+
+#[test]
+fn test_chunk_set_block() {
+    let mut chunk = REGION.get_chunk(0, 0).unwrap().unwrap().parse().unwrap();
+
+    chunk.set_block(4, 84, 10, block("stone"));
+    assert_eq!(chunk.get_block(4, 84, 10), Some(&block("stone")));
+    assert_eq!(
+        chunk.get_block(5, 84, 10).unwrap().name,
+        "grass_block".into()
+    );
+
+    // Setting a block above the world creates a new section
+    assert_eq!(chunk.get_block(0, 400, 0), None);
+    chunk.set_block(0, 400, 0, block("glass"));
+    assert_eq!(chunk.get_block(0, 400, 0), Some(&block("glass")));
+    assert_eq!(chunk.get_block(1, 400, 0), Some(&block("air")));
+}
+
+#[test]
+fn test_section_normalize() {
+    let mut section = nbt::ChunkSection::new(0);
+    for i in 0..32 {
+        section.set_block(i % 16, i / 16, 0, block(&format!("block_{i}")));
+    }
+    // Overwrite everything except `block_0` and `block_1`
+    for i in 2..32 {
+        section.set_block(i % 16, i / 16, 0, block("stone"));
+    }
+
+    assert_eq!(section.block_states.as_ref().unwrap().palette.len(), 34);
+    section.normalize();
+
+    let bs = section.block_states.as_ref().unwrap();
+    assert_eq!(
+        bs.palette,
+        [
+            block("air"),
+            block("block_0"),
+            block("block_1"),
+            block("stone")
+        ]
+    );
+    // Still packed at the minimum of 4 bits
+    assert_eq!(bs.data.as_ref().unwrap().len(), 256);
+    assert_eq!(section.get_block(0, 0, 0), Some(&block("block_0")));
+    assert_eq!(section.get_block(1, 0, 0), Some(&block("block_1")));
+    assert_eq!(section.get_block(15, 1, 0), Some(&block("stone")));
+    assert_eq!(section.get_block(15, 15, 15), Some(&block("air")));
+
+    // A section of only one block state has no data
+    for y in 0..16 {
+        for z in 0..16 {
+            for x in 0..16 {
+                section.set_block(x, y, z, block("stone"));
+            }
+        }
+    }
+    section.normalize();
+
+    let bs = section.block_states.as_ref().unwrap();
+    assert_eq!(bs.palette, [block("stone")]);
+    assert_eq!(bs.data, None);
+}
+
+#[test]
+fn test_biomes() {
+    let palette = vec!["minecraft:plains".to_string(), "minecraft:forest".into()];
+    // Every cell with an odd x is a forest
+    let data: Vec<i64> = vec![0xaaaa_aaaa_aaaa_aaaau64 as i64];
+    let section = nbt::ChunkSection {
+        biomes: Some(nbt::Biomes {
+            palette,
+            data: Some(fastnbt::LongArray::new(data)),
+        }),
+        ..nbt::ChunkSection::new(0)
+    };
+
+    assert_eq!(section.get_biome(0, 0, 0), Some("minecraft:plains"));
+    assert_eq!(section.get_biome(4, 15, 7), Some("minecraft:forest"));
+    assert_eq!(section.get_biome(11, 3, 2), Some("minecraft:plains"));
+
+    let cells: Vec<_> = section.biome_cells().collect();
+    assert_eq!(cells.len(), 64);
+    for (x, y, z, biome) in cells {
+        assert_eq!(Some(biome), section.get_biome(x * 4, y * 4, z * 4));
+    }
+
+    let chunk = REGION.get_chunk(0, 0).unwrap().unwrap().parse().unwrap();
+    assert!(chunk.get_biome(4, 84, 10).is_some());
+    assert_eq!(chunk.get_biome(4, 1000, 10), None);
+}
+
+#[test]
+fn test_set_biome() {
+    let mut section = nbt::ChunkSection::new(0);
+    section.set_biome(0, 0, 0, "minecraft:plains");
+    assert_eq!(section.biomes.as_ref().unwrap().data, None);
+
+    section.set_biome(0, 0, 0, "minecraft:mushroom_fields");
+    section.set_biome(15, 15, 15, "minecraft:forest");
+    section.set_biome(4, 0, 0, "minecraft:desert");
+
+    let biomes = section.biomes.as_ref().unwrap();
+    // `plains` is still used by other cells, so it stays in the palette
+    assert_eq!(biomes.palette.len(), 4);
+    // 2 bits per cell without a minimum
+    assert_eq!(biomes.data.as_ref().unwrap().len(), 2);
+
+    assert_eq!(
+        section.get_biome(0, 0, 0),
+        Some("minecraft:mushroom_fields")
+    );
+    assert_eq!(
+        section.get_biome(3, 3, 3),
+        Some("minecraft:mushroom_fields")
+    );
+    assert_eq!(section.get_biome(4, 0, 0), Some("minecraft:desert"));
+    assert_eq!(section.get_biome(12, 12, 12), Some("minecraft:forest"));
+    assert_eq!(section.get_biome(8, 0, 0), Some("minecraft:plains"));
+
+    // Converting every cell to one biome collapses the palette
+    let cells: Vec<_> = section
+        .biome_cells()
+        .map(|(x, y, z, _)| (x, y, z))
+        .collect();
+    for (x, y, z) in cells {
+        section.set_biome(x * 4, y * 4, z * 4, "minecraft:mushroom_fields");
+    }
+
+    let biomes = section.biomes.as_ref().unwrap();
+    assert_eq!(biomes.palette, ["minecraft:mushroom_fields"]);
+    assert_eq!(biomes.data, None);
+}
+
+#[test]
+fn test_light() {
+    let mut light = vec![0i8; 2048];
+    // (1, 0, 0) is the high nibble of the first byte, (0, 1, 0) is the low nibble of byte 128
+    light[0] = 0x70;
+    light[128] = 0x0f;
+
+    let section = nbt::ChunkSection {
+        block_light: Some(fastnbt::ByteArray::new(light)),
+        ..nbt::ChunkSection::new(0)
+    };
+
+    assert_eq!(section.get_block_light(0, 0, 0), 0);
+    assert_eq!(section.get_block_light(1, 0, 0), 7);
+    assert_eq!(section.get_block_light(0, 1, 0), 15);
+    assert_eq!(section.get_block_light(1, 1, 0), 0);
+    // Missing arrays are all zero
+    assert_eq!(section.get_sky_light(1, 0, 0), 0);
+
+    let chunk = REGION.get_chunk(0, 0).unwrap().unwrap().parse().unwrap();
+    assert_eq!(chunk.is_light_on, Some(true));
+    assert!(chunk.get_sky_light(4, 200, 10).is_some());
+    assert_eq!(chunk.get_block_light(4, 1000, 10), None);
+}
+
+/// Create a chunk with two sections (y = 0..=31) which are filled with air
+fn empty_chunk(x_pos: i32, z_pos: i32) -> ParsedChunk {
+    ParsedChunk::from(nbt::ChunkNbt {
+        data_version: EXPECTED_DATA_VERSION,
+        x_pos,
+        z_pos,
+        y_pos: 0,
+        status: "full".into(),
+        last_update: 0,
+        block_entities: Vec::new(),
+        entities: Vec::new(),
+        height_maps: nbt::HeightMaps {
+            motion_blocking: None,
+            motion_blocking_no_leaves: None,
+            ocean_floor: None,
+            ocean_floor_wg: None,
+            world_surface: None,
+            world_surface_wg: None,
+        },
+        fluid_ticks: Vec::new(),
+        block_ticks: Vec::new(),
+        inhabited_time: 0,
+        is_light_on: None,
+        blending_data: None,
+        post_processing: vec![Vec::new(); 2],
+        structures: Default::default(),
+        sections: vec![nbt::ChunkSection::new(0), nbt::ChunkSection::new(1)],
+    })
+}
+
+#[test]
+fn test_relight() {
+    let mut chunk = empty_chunk(0, 0);
+    for x in 0..16 {
+        for z in 0..16 {
+            chunk.set_block(x, 10, z, block("stone"));
+        }
+    }
+    chunk.set_block(8, 5, 8, block("glowstone"));
+
+    light::relight(&mut chunk, &[]);
+
+    assert_eq!(chunk.is_light_on, Some(true));
+    assert_eq!(chunk.get_sky_light(3, 31, 3), Some(15));
+    assert_eq!(chunk.get_sky_light(3, 11, 3), Some(15));
+    assert_eq!(chunk.get_sky_light(3, 10, 3), Some(0));
+    assert_eq!(chunk.get_sky_light(3, 9, 3), Some(0));
+
+    assert_eq!(chunk.get_block_light(8, 5, 8), Some(15));
+    assert_eq!(chunk.get_block_light(8, 6, 8), Some(14));
+    assert_eq!(chunk.get_block_light(8, 5, 10), Some(13));
+    assert_eq!(chunk.get_block_light(8, 11, 8), Some(0));
+
+    // Light from a neighbour crosses the border
+    let mut neighbour = empty_chunk(-1, 0);
+    neighbour.set_block(15, 20, 4, block("glowstone"));
+    let mut chunk = empty_chunk(0, 0);
+    chunk.set_block(0, 22, 4, block("stone"));
+
+    light::relight(&mut chunk, &[&neighbour]);
+    assert_eq!(chunk.get_block_light(0, 20, 4), Some(14));
+    assert_eq!(chunk.get_block_light(1, 20, 4), Some(13));
+    // The stone blocks the direct path, so the light goes around it
+    assert_eq!(chunk.get_block_light(0, 22, 4), Some(0));
+    assert_eq!(chunk.get_block_light(0, 23, 4), Some(11));
+
+    // Without the neighbour, there's no light
+    light::relight(&mut chunk, &[]);
+    assert_eq!(chunk.get_block_light(0, 20, 4), Some(0));
+}
+
+#[test]
+fn test_dimension_relight_chunk() {
+    use fastnbt::{LongArray, Value};
+
+    // A torch at x = 15 of the chunk at (0, 0), with 4 bits for each block
+    let index = 5 * 256 + 8 * 16 + 15;
+    let mut data = vec![0i64; 256];
+    data[index / 16] = 1 << ((index % 16) * 4);
+
+    let mut chunk = chunk_nbt(0, 0);
+    let Value::Compound(c) = &mut chunk else {
+        unreachable!()
+    };
+    c.insert(
+        "sections".into(),
+        fastnbt::nbt!([{
+            "Y": 0i8,
+            "block_states": {
+                "palette": [{ "Name": "minecraft:air" }, { "Name": "minecraft:torch" }],
+                "data": Value::LongArray(LongArray::new(data)),
+            },
+        }]),
+    );
+
+    let mut dim = Dimension::from_iter(
+        None,
+        [MemoryRegion::new(
+            0,
+            0,
+            &[
+                (0, 0, chunk),
+                (1, 0, chunk_nbt(1, 0)),
+                (2, 0, chunk_nbt(2, 0)),
+            ],
+        )]
+        .into_iter(),
+    );
+
+    let chunks = dim.relight_chunk(0, 0).unwrap().unwrap();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!((chunks[0].x_pos, chunks[0].z_pos), (0, 0));
+    assert_eq!(chunks[0].get_block_light(15, 5, 8), Some(14));
+
+    // The light crosses into the neighbour, which is relit too
+    let neighbour = &chunks[1];
+    assert_eq!((neighbour.x_pos, neighbour.z_pos), (1, 0));
+    assert_eq!(neighbour.get_block_light(0, 5, 8), Some(13));
+    assert_eq!(neighbour.is_light_on, Some(true));
+
+    assert!(dim.relight_chunk(5, 5).unwrap().is_none());
+}
+
+#[test]
+fn test_recompute_height_maps() {
+    let mut chunk = empty_chunk(0, 0);
+    chunk.y_pos = -4;
+    chunk.sections = (-4..20).map(nbt::ChunkSection::new).collect();
+    assert_eq!(chunk.world_height(), nbt::WorldHeight::OVERWORLD);
+
+    chunk.set_block(0, 10, 0, block("stone"));
+    chunk.set_block(0, 12, 0, block("oak_leaves"));
+    chunk.set_block(1, 5, 1, block("water"));
+    chunk.set_block(2, 2, 2, block("stone"));
+    chunk.set_block(2, 3, 2, block("white_carpet"));
+    chunk.set_block(3, -60, 3, block("torch"));
+
+    chunk.recompute_height_maps();
+
+    let hm = &chunk.height_maps;
+    let heights = |x, z| {
+        [
+            &hm.world_surface,
+            &hm.ocean_floor,
+            &hm.motion_blocking,
+            &hm.motion_blocking_no_leaves,
+        ]
+        .map(|m| {
+            m.as_ref()
+                .unwrap()
+                .get_height(x, z, chunk.world_height())
+                .unwrap()
+        })
+    };
+
+    assert_eq!(heights(0, 0), [12, 12, 12, 10]);
+    assert_eq!(heights(1, 1), [5, -65, 5, 5]);
+    assert_eq!(heights(2, 2), [3, 2, 2, 2]);
+    assert_eq!(heights(3, 3), [-60, -65, -65, -65]);
+    assert_eq!(heights(5, 5), [-65; 4]);
+    assert_eq!(hm.world_surface, hm.world_surface_wg);
+    assert_eq!(hm.ocean_floor, hm.ocean_floor_wg);
+}
+
+#[test]
+fn test_world_height_sparse_sections() {
+    let mut chunk = empty_chunk(0, 0);
+    chunk.y_pos = -4;
+    // A light-only section below the world, and sections missing between the bottom and top
+    let mut light_only = nbt::ChunkSection::new(-5);
+    light_only.block_states = None;
+    chunk.sections = vec![
+        light_only,
+        nbt::ChunkSection::new(-4),
+        nbt::ChunkSection::new(19),
+    ];
+    assert_eq!(chunk.world_height(), nbt::WorldHeight::OVERWORLD);
+
+    // A trimmed chunk without its top sections needs the height of the dimension
+    let world = nbt::WorldHeight::OVERWORLD;
+    chunk.sections.truncate(2);
+    chunk.set_block(0, 10, 0, block("stone"));
+    chunk.recompute_height_maps_in(world);
+    let ws = chunk.height_maps.world_surface.as_ref().unwrap();
+    assert_eq!(ws.get_height(0, 0, world), Some(10));
+    assert_eq!(ws.get_height(1, 0, world), Some(-65));
+}
+
+#[test]
+fn test_set_height() {
+    let world = nbt::WorldHeight::OVERWORLD;
+    let mut heights = [0; 256];
+    for (i, h) in heights.iter_mut().enumerate() {
+        *h = i as i32 - 64;
+    }
+
+    let mut hm = nbt::HeightMap::new(&heights, world);
+    assert_eq!(hm.get_height(0, 0, world), Some(-64));
+    assert_eq!(hm.get_height(15, 15, world), Some(191));
+
+    hm.set_height(4, 4, 319, world);
+    assert_eq!(hm.get_height(4, 4, world), Some(319));
+    assert_eq!(hm.get_height(3, 4, world), Some(67 - 64));
+    assert_eq!(hm.get_height(5, 4, world), Some(69 - 64));
+
+    // A world which is too short, i.e. because a chunk is missing its top sections
+    let short = nbt::WorldHeight {
+        min_y: -64,
+        height: 16,
+    };
+    assert_eq!(hm.get_height(0, 0, short), None);
+}
+
+#[test]
+fn test_custom_height_chunk() {
+    use fastnbt::{LongArray, Value};
+
+    // A dimension with `min_y: -2032` and `height: 4064` needs 12 bits per heightmap value, so
+    // only 5 values fit in each long
+    let mut motion_blocking = vec![0i64; 52];
+    // (0, 0) is at the top of the world and (1, 0) is at the bottom
+    motion_blocking[0] = 4064 | 1 << 12;
+    // (5, 0) is at y = 0
+    motion_blocking[1] = 2033;
+
+    let sections: Vec<Value> = (-127i8..127)
+        .map(|y| {
+            fastnbt::nbt!({
+                "Y": y,
+                "block_states": { "palette": [{ "Name": "minecraft:air" }] },
+            })
+        })
+        .collect();
+
+    let value = fastnbt::nbt!({
+        "DataVersion": EXPECTED_DATA_VERSION,
+        "xPos": 0,
+        "zPos": 0,
+        "yPos": -127,
+        "Status": "minecraft:full",
+        "LastUpdate": 0i64,
+        "block_entities": Value::List(Vec::new()),
+        "Heightmaps": {
+            "MOTION_BLOCKING": Value::LongArray(LongArray::new(motion_blocking)),
+        },
+        "fluid_ticks": Value::List(Vec::new()),
+        "block_ticks": Value::List(Vec::new()),
+        "InhabitedTime": 0i64,
+        "PostProcessing": vec![Value::List(Vec::new()); 254],
+        "structures": {},
+        "sections": sections,
+    });
+
+    let nbt: nbt::ChunkNbt = fastnbt::from_bytes(&fastnbt::to_bytes(&value).unwrap()).unwrap();
+    let world = nbt.world_height();
+    assert_eq!(
+        world,
+        nbt::WorldHeight {
+            min_y: -2032,
+            height: 4064
+        }
+    );
+    assert_eq!(world.heightmap_bits(), 12);
+
+    let mb = nbt.height_maps.motion_blocking.as_ref().unwrap();
+    assert_eq!(mb.get_height(0, 0, world), Some(2031));
+    assert_eq!(mb.get_height(1, 0, world), Some(-2032));
+    assert_eq!(mb.get_height(5, 0, world), Some(0));
+    assert_eq!(mb.get_height(6, 0, world), Some(-2033));
+
+    let mut chunk = ParsedChunk::from(nbt);
+    chunk.set_block(2, 1000, 2, block("stone"));
+    chunk.set_block(3, -2032, 2, block("stone"));
+    chunk.recompute_height_maps();
+
+    let ws = chunk.height_maps.world_surface.as_ref().unwrap();
+    assert_eq!(ws.get_height(2, 2, world), Some(1000));
+    assert_eq!(ws.get_height(3, 2, world), Some(-2032));
+    assert_eq!(ws.get_height(4, 2, world), Some(-2033));
+}
+
+#[test]
+fn test_legacy_chunk() {
+    use fastnbt::{IntArray, LongArray, Value};
+
+    // 4 bits per block, so 16 blocks in each long.  The first block is stone.
+    let mut block_states = vec![0i64; 256];
+    block_states[0] = 1;
+
+    // The first 4x4x4 biome cell is a desert and the rest are plains
+    let mut biomes = vec![1; 1024];
+    biomes[0] = 2;
+
+    let value = fastnbt::nbt!({
+        "DataVersion": 2586,
+        "Level": {
+            "xPos": 3,
+            "zPos": -2,
+            "LastUpdate": 0i64,
+            "InhabitedTime": 0i64,
+            "Status": "full",
+            "Biomes": Value::IntArray(IntArray::new(biomes)),
+            "Sections": [
+                { "Y": -1i8 },
+                {
+                    "Y": 2i8,
+                    "Palette": [{ "Name": "minecraft:air" }, { "Name": "minecraft:stone" }],
+                    "BlockStates": Value::LongArray(LongArray::new(block_states)),
+                },
+            ],
+        },
+    });
+
+    let chunk = ParsedChunk::from_bytes(&fastnbt::to_bytes(&value).unwrap()).unwrap();
+    assert_eq!(chunk.data_version, 2586);
+    assert_eq!(chunk.saved_by(), Some("1.16.5"));
+    assert_eq!((chunk.x_pos, chunk.z_pos, chunk.y_pos), (3, -2, 0));
+    assert_eq!(
+        chunk.world_height(),
+        nbt::WorldHeight {
+            min_y: 0,
+            height: 256
         }
     );
 
-    assert_eq!(chunk.get_block(13, 200, 15), None)
+    assert_eq!(chunk.get_block(0, 32, 0), Some(&block("stone")));
+    assert_eq!(chunk.get_block(1, 32, 0), Some(&block("air")));
+    // Sections which were not saved are filled with air
+    assert_eq!(chunk.get_block(0, 200, 0), Some(&block("air")));
+
+    assert_eq!(chunk.get_biome(0, 0, 0), Some("minecraft:desert"));
+    assert_eq!(chunk.get_biome(4, 0, 0), Some("minecraft:plains"));
+    assert_eq!(chunk.get_biome(0, 100, 0), Some("minecraft:plains"));
+}
+
+/// Pack `items` continuously across longs, as was done before 20w17a
+fn pack_spanning(items: &[u64], bits: usize) -> Vec<i64> {
+    let mut packed = vec![0i64; (items.len() * bits).div_ceil(64)];
+    for (i, item) in items.iter().enumerate() {
+        let start = i * bits;
+        packed[start / 64] |= (item << (start % 64)) as i64;
+        if start % 64 + bits > 64 {
+            packed[start / 64 + 1] |= (item >> (64 - start % 64)) as i64;
+        }
+    }
+    packed
+}
+
+#[test]
+fn test_legacy_spanning_chunk() {
+    use fastnbt::{LongArray, Value};
+
+    // 17 entries in the palette need 5 bits, so the 13th block (bits 60..65) spans two longs
+    let palette: Vec<Value> = [
+        "air",
+        "stone",
+        "dirt",
+        "grass_block",
+        "sand",
+        "gravel",
+        "gold_ore",
+    ]
+    .iter()
+    .chain(&[
+        "iron_ore",
+        "coal_ore",
+        "oak_log",
+        "oak_leaves",
+        "glass",
+        "lapis_ore",
+    ])
+    .chain(&["sandstone", "note_block", "cobweb", "diamond_ore"])
+    .map(|name| fastnbt::nbt!({ "Name": format!("minecraft:{}", name) }))
+    .collect();
+    let mut indices = vec![0; 4096];
+    indices[12] = 16;
+    indices[13] = 1;
+    let block_states = pack_spanning(&indices, 5);
+    assert_eq!(block_states.len(), 320);
+
+    // 9 bits per value, so the 8th column (bits 63..72) spans two longs
+    let mut heights = vec![0; 256];
+    heights[7] = 70;
+    let motion_blocking = pack_spanning(&heights, 9);
+    assert_eq!(motion_blocking.len(), 36);
+
+    let value = fastnbt::nbt!({
+        "DataVersion": 2230,
+        "Level": {
+            "xPos": 0,
+            "zPos": 0,
+            "Status": "full",
+            "Heightmaps": {
+                "MOTION_BLOCKING": Value::LongArray(LongArray::new(motion_blocking)),
+            },
+            "Sections": [{
+                "Y": 0i8,
+                "Palette": palette,
+                "BlockStates": Value::LongArray(LongArray::new(block_states)),
+            }],
+        },
+    });
+
+    let chunk = ParsedChunk::from_bytes(&fastnbt::to_bytes(&value).unwrap()).unwrap();
+    assert_eq!(chunk.get_block(12, 0, 0), Some(&block("diamond_ore")));
+    assert_eq!(chunk.get_block(13, 0, 0), Some(&block("stone")));
+    assert_eq!(chunk.get_block(11, 0, 0), Some(&block("air")));
+
+    let world = chunk.world_height();
+    let mb = chunk.height_maps.motion_blocking.as_ref().unwrap();
+    assert_eq!(mb.get_height(7, 0, world), Some(69));
+    assert_eq!(mb.get_height(8, 0, world), Some(-1));
+}
+
+#[test]
+fn test_numeric_id_chunk() {
+    use fastnbt::{ByteArray, Value};
+
+    let mut blocks = vec![0i8; 4096];
+    let mut data = vec![0i8; 2048];
+    // Diorite at index 0 (low nibble) and an oak log on the x axis at index 1 (high nibble)
+    blocks[0] = 1;
+    blocks[1] = 17;
+    data[0] = 3 | 4 << 4;
+    // Wool at index 256 (x = 0, y = 1, z = 0) with a data value of 14 (red)
+    blocks[256] = 35;
+    data[128] = 14;
+    // An id above 255 (which isn't known, so is kept as a placeholder) at index 2, using `Add`
+    let mut add = vec![0i8; 2048];
+    blocks[2] = 1;
+    add[1] = 1;
+
+    let mut biomes = vec![1i8; 256];
+    biomes[0] = 2;
+
+    let value = fastnbt::nbt!({
+        "Level": {
+            "xPos": 0,
+            "zPos": 0,
+            "Biomes": Value::ByteArray(ByteArray::new(biomes)),
+            "Sections": [{
+                "Y": 1i8,
+                "Blocks": Value::ByteArray(ByteArray::new(blocks)),
+                "Data": Value::ByteArray(ByteArray::new(data)),
+                "Add": Value::ByteArray(ByteArray::new(add)),
+            }],
+        },
+    });
+
+    let chunk = ParsedChunk::from_bytes(&fastnbt::to_bytes(&value).unwrap()).unwrap();
+    assert_eq!(chunk.data_version, 0);
+    assert_eq!(chunk.get_block(0, 16, 0), Some(&block("diorite")));
+    let log = chunk.get_block(1, 16, 0).unwrap();
+    assert_eq!(log.name, "oak_log".into());
+    assert_eq!(log.get("axis"), Some("x"));
+    assert_eq!(
+        chunk.get_block(2, 16, 0),
+        Some(&"legacy:257[data=0]".parse().unwrap())
+    );
+    assert_eq!(chunk.get_block(0, 17, 0), Some(&block("red_wool")));
+    assert_eq!(chunk.get_block(0, 0, 0), Some(&block("air")));
+
+    assert_eq!(chunk.get_biome(0, 0, 0), Some("minecraft:desert"));
+    assert_eq!(chunk.get_biome(4, 0, 0), Some("minecraft:plains"));
+}
+
+#[test]
+fn test_parse_directory_mcregion() {
+    let dir = std::env::temp_dir().join(format!("mca-parser-mcr-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["r.0.0.mca", "r.0.0.mcr", "r.1.0.mcr"] {
+        File::create(dir.join(name)).unwrap();
+    }
+
+    let mut files: Vec<_> = parse_directory(&dir)
+        .unwrap()
+        .map(|rf| rf.path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The MCRegion file is ignored when it has been converted to anvil
+    assert_eq!(files, ["r.0.0.mca", "r.1.0.mcr"]);
+}
+
+#[test]
+fn test_dimension_prefers_anvil() {
+    let dir = std::env::temp_dir().join(format!("mca-parser-anvil-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["r.0.0.mca", "r.0.0.mcr"] {
+        File::create(dir.join(name)).unwrap();
+    }
+
+    let region_name = |dim: &Dimension<RegionFile>| -> Vec<String> {
+        dim.regions()
+            .map(|rf| rf.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    };
+
+    let dim = Dimension::from_path(&dir).unwrap();
+    assert_eq!(region_name(&dim), ["r.0.0.mca"]);
+
+    // The anvil region is kept whichever order the regions are given in
+    for names in [["r.0.0.mca", "r.0.0.mcr"], ["r.0.0.mcr", "r.0.0.mca"]] {
+        let dim = Dimension::from_iter(None, names.iter().map(|n| RegionFile::new(dir.join(n))));
+        assert_eq!(region_name(&dim), ["r.0.0.mca"]);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_mcregion_chunk() {
+    use fastnbt::{ByteArray, Value};
+
+    // Blocks are ordered by x, then z, then y
+    let index = |x: usize, y: usize, z: usize| x * 2048 + z * 128 + y;
+    let mut blocks = vec![0i8; 32768];
+    let mut data = vec![0i8; 16384];
+    blocks[index(0, 0, 0)] = 7;
+    blocks[index(3, 70, 5)] = 35;
+    // Orange wool
+    let i = index(3, 70, 5);
+    data[i / 2] = 1 << ((i % 2) * 4);
+    let mut sky_light = vec![0i8; 16384];
+    let i = index(3, 71, 5);
+    sky_light[i / 2] = 15 << ((i % 2) * 4);
+
+    let value = fastnbt::nbt!({
+        "Level": {
+            "xPos": 1,
+            "zPos": 2,
+            "Blocks": Value::ByteArray(ByteArray::new(blocks)),
+            "Data": Value::ByteArray(ByteArray::new(data)),
+            "SkyLight": Value::ByteArray(ByteArray::new(sky_light)),
+        },
+    });
+
+    let chunk = ParsedChunk::from_bytes(&fastnbt::to_bytes(&value).unwrap()).unwrap();
+    assert_eq!(chunk.get_block(0, 0, 0), Some(&block("bedrock")));
+    assert_eq!(chunk.get_block(3, 70, 5), Some(&block("orange_wool")));
+    assert_eq!(chunk.get_block(5, 70, 3), Some(&block("air")));
+    // Blocks above the height of MCRegion chunks are air
+    assert_eq!(chunk.get_block(3, 200, 5), Some(&block("air")));
+    assert_eq!(chunk.get_sky_light(3, 71, 5), Some(15));
+    assert_eq!(chunk.get_sky_light(3, 70, 5), Some(0));
+}
+
+#[test]
+fn test_upgrade_chunk() {
+    use block_entity::BlockEntity;
+    use fastnbt::{ByteArray, Value};
+    use nbt::{TickId, TickKind};
+    use upgrade::{Upgrader, CURRENT_DATA_VERSION};
+
+    // Tall grass, a standing sign and a cauldron with water in it
+    let mut blocks = vec![0i8; 4096];
+    let mut data = vec![0i8; 2048];
+    blocks[0] = 31;
+    blocks[1] = 63;
+    blocks[2] = 118;
+    data[0] = 1;
+    data[1] = 2;
+
+    let value = fastnbt::nbt!({
+        "DataVersion": 512,
+        "Level": {
+            "xPos": 0,
+            "zPos": 0,
+            "Status": "postprocessed",
+            "Biomes": Value::ByteArray(ByteArray::new(vec![3; 256])),
+            "Sections": [{
+                "Y": 0i8,
+                "Blocks": Value::ByteArray(ByteArray::new(blocks)),
+                "Data": Value::ByteArray(ByteArray::new(data)),
+            }],
+            "TileEntities": [{ "id": "Chest", "x": 3, "y": 0, "z": 0, "Items": [] }],
+            "TileTicks": [
+                { "i": "minecraft:flowing_water", "t": 1, "x": 4, "y": 0, "z": 0 },
+                { "i": 93, "t": 2, "x": 5, "y": 0, "z": 0 },
+            ],
+        },
+    });
+
+    let mut chunk = ParsedChunk::from_bytes(&fastnbt::to_bytes(&value).unwrap()).unwrap();
+    assert_eq!(chunk.get_block(0, 0, 0), Some(&block("grass")));
+    assert_eq!(chunk.get_biome(0, 0, 0), Some("minecraft:mountains"));
+    assert!(matches!(chunk.block_entities[0], BlockEntity::Unknown(_)));
+
+    let upgrader = Upgrader::default();
+    assert_eq!(upgrader.target_data_version(), Some(CURRENT_DATA_VERSION));
+    upgrader.upgrade(&mut chunk);
+
+    assert_eq!(chunk.data_version, CURRENT_DATA_VERSION);
+    assert_eq!(chunk.status, "full".into());
+    assert_eq!(chunk.get_block(0, 0, 0), Some(&block("short_grass")));
+    assert_eq!(chunk.get_block(1, 0, 0), Some(&block("oak_sign")));
+    assert_eq!(
+        chunk.get_block(2, 0, 0).unwrap().name,
+        "water_cauldron".into()
+    );
+    assert_eq!(chunk.get_biome(0, 0, 0), Some("minecraft:windswept_hills"));
+
+    // The chunk is extended below y = 0 with air, and the biomes of the lowest section
+    assert_eq!(chunk.y_pos, -4);
+    assert_eq!(chunk.sections.len(), 24);
+    assert_eq!(chunk.world_height(), nbt::WorldHeight::OVERWORLD);
+    assert_eq!(chunk.get_block(0, -64, 0), Some(&block("air")));
+    assert_eq!(chunk.get_block(0, 319, 0), Some(&block("air")));
+    assert_eq!(
+        chunk.get_biome(0, -64, 0),
+        Some("minecraft:windswept_hills")
+    );
+    assert_eq!(chunk.post_processing.len(), 24);
+
+    assert!(matches!(chunk.block_entities[0], BlockEntity::Chest(_)));
+    assert_eq!(chunk.block_entities[0].id(), Some("chest".into()));
+    let fluid_ticks = chunk.ticks(TickKind::Fluid);
+    assert_eq!(fluid_ticks.len(), 1);
+    assert_eq!(fluid_ticks[0].id, TickId::from("water"));
+    assert_eq!(chunk.ticks(TickKind::Block)[0].id, TickId::from("repeater"));
+
+    // Heightmaps are moved to the new bottom of the world
+    let old_world = nbt::WorldHeight {
+        min_y: 0,
+        height: 256,
+    };
+    let mut heights = [-1; 256];
+    heights[0] = 64;
+    let mut chunk = empty_chunk(0, 0);
+    chunk.data_version = 2586;
+    chunk.height_maps.world_surface = Some(nbt::HeightMap::new(&heights, old_world));
+    upgrader.upgrade(&mut chunk);
+
+    let ws = chunk.height_maps.world_surface.as_ref().unwrap();
+    let world = nbt::WorldHeight::OVERWORLD;
+    assert_eq!(ws.get_height(0, 0, world), Some(64));
+    assert_eq!(ws.get_height(1, 0, world), Some(-65));
+
+    // Chunks with the layout since 1.18 are upgraded to the latest version
+    let mut chunk = empty_chunk(0, 0);
+    chunk.data_version = 2844;
+    chunk.set_block(0, 0, 0, block("grass"));
+    chunk.set_biome(0, 0, 0, "minecraft:mountains");
+    upgrader.upgrade(&mut chunk);
+
+    assert_eq!(chunk.data_version, CURRENT_DATA_VERSION);
+    assert_eq!(chunk.get_block(0, 0, 0), Some(&block("short_grass")));
+    assert_eq!(chunk.get_biome(0, 0, 0), Some("minecraft:windswept_hills"));
+
+    // Steps that are not newer than the chunk are not run
+    let mut upgrader = Upgrader::new();
+    upgrader.add_step(upgrade::Step {
+        data_version: 100,
+        upgrade: |chunk| chunk.x_pos = 100,
+    });
+    upgrader.upgrade(&mut chunk);
+    assert_eq!(chunk.x_pos, 0);
+    assert_eq!(chunk.data_version, CURRENT_DATA_VERSION);
+}
+
+#[test]
+fn test_tile_ticks() {
+    use nbt::{TickKind, TileTick};
+
+    let tick = |id: &str, x, y, z, ticks| TileTick {
+        id: id.into(),
+        priority: 0,
+        ticks,
+        x,
+        y,
+        z,
+    };
+
+    let mut chunk = empty_chunk(1, -1);
+    chunk.add_tick(TickKind::Block, tick("minecraft:repeater", 17, 5, -16, 2));
+    chunk.add_tick(TickKind::Block, tick("minecraft:comparator", 17, 5, -16, 1));
+    chunk.add_tick(TickKind::Block, tick("minecraft:observer", 20, 20, -10, 3));
+    chunk.add_tick(TickKind::Fluid, tick("minecraft:water", 17, 5, -16, 5));
+    // Replaces the first repeater tick
+    chunk.add_tick(TickKind::Block, tick("minecraft:repeater", 17, 5, -16, 4));
+
+    assert_eq!(chunk.ticks(TickKind::Block).len(), 3);
+    let at: Vec<_> = chunk.ticks_at(TickKind::Block, 1, 5, 0).collect();
+    assert_eq!(at.len(), 2);
+    assert!(at.contains(&&tick("minecraft:repeater", 17, 5, -16, 4)));
+    assert_eq!(chunk.ticks_in_section(TickKind::Block, 1).count(), 1);
+    assert_eq!(chunk.ticks_in_section(TickKind::Fluid, 0).count(), 1);
+
+    let removed = chunk.remove_ticks_at(TickKind::Block, 1, 5, 0);
+    assert_eq!(removed.len(), 2);
+    assert_eq!(
+        chunk.ticks(TickKind::Block),
+        [tick("minecraft:observer", 20, 20, -10, 3)]
+    );
+    assert_eq!(chunk.ticks(TickKind::Fluid).len(), 1);
+}
+
+#[test]
+fn test_numeric_tile_ticks() {
+    use nbt::{TickId, TickKind};
+
+    // Before 1.8, ticks used the numeric id of the block and had no priority
+    let value = fastnbt::nbt!({
+        "Level": {
+            "xPos": 0,
+            "zPos": 0,
+            "Sections": [],
+            "TileTicks": [
+                { "i": 55, "t": 3, "x": 1, "y": 64, "z": 2 },
+                { "i": "minecraft:water", "p": 1, "t": 5, "x": 3, "y": 60, "z": 4 },
+            ],
+        },
+    });
+
+    let chunk = ParsedChunk::from_bytes(&fastnbt::to_bytes(&value).unwrap()).unwrap();
+    let ticks = chunk.ticks(TickKind::Block);
+    assert_eq!(ticks.len(), 2);
+
+    assert_eq!(ticks[0].id, TickId::Numeric(55));
+    assert_eq!(ticks[0].id.name(), "redstone_wire".into());
+    assert_eq!(ticks[0].priority, 0);
+    assert_eq!((ticks[0].x, ticks[0].y, ticks[0].z), (1, 64, 2));
+
+    assert_eq!(ticks[1].id, "minecraft:water".into());
+    assert_eq!(ticks[1].priority, 1);
+}
+
+/// A region which is built in memory, so that dimensions can be tested without the test files
+struct MemoryRegion {
+    position: (i32, i32),
+    data: Vec<u8>,
+}
+
+impl MemoryRegion {
+    /// Create a region containing the chunks at (x, z) relative to the region
+    fn new(region_x: i32, region_z: i32, chunks: &[(u32, u32, fastnbt::Value)]) -> Self {
+        let mut data = vec![0u8; 8192];
+        for (x, z, nbt) in chunks {
+            let compressed =
+                miniz_oxide::deflate::compress_to_vec_zlib(&fastnbt::to_bytes(nbt).unwrap(), 6);
+            let offset = data.len() / 4096;
+            let sectors = (compressed.len() + 5).div_ceil(4096);
+
+            let index = (*x as usize + *z as usize * 32) * 4;
+            data[index..index + 3].copy_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            data[index + 3] = sectors as u8;
+
+            data.extend(((compressed.len() + 1) as u32).to_be_bytes());
+            data.push(2);
+            data.extend(compressed);
+            data.resize((offset + sectors) * 4096, 0);
+        }
+
+        Self {
+            position: (region_x, region_z),
+            data,
+        }
+    }
+}
+
+impl RegionParser for MemoryRegion {
+    fn parse(&self) -> Result<RegionRef<'_>> {
+        Ok(Region::from_slice(&self.data)?.into())
+    }
+
+    fn position(&self) -> Option<(i32, i32)> {
+        Some(self.position)
+    }
+}
+
+/// Create the nbt for a chunk which only contains air, for use with [`MemoryRegion`]
+fn chunk_nbt(x_pos: i32, z_pos: i32) -> fastnbt::Value {
+    fastnbt::nbt!({
+        "DataVersion": EXPECTED_DATA_VERSION,
+        "xPos": x_pos,
+        "zPos": z_pos,
+        "yPos": 0,
+        "Status": "minecraft:full",
+        "LastUpdate": 0i64,
+        "block_entities": [],
+        "Heightmaps": {},
+        "fluid_ticks": [],
+        "block_ticks": [],
+        "InhabitedTime": 0i64,
+        "PostProcessing": [],
+        "structures": {},
+        "sections": [{
+            "Y": 0i8,
+            "block_states": { "palette": [{ "Name": "minecraft:air" }] },
+        }],
+    })
+}
+
+#[test]
+fn test_structures() {
+    use fastnbt::{IntArray, LongArray, Value};
+
+    let bb = |arr: [i32; 6]| Value::IntArray(IntArray::new(arr.to_vec()));
+
+    let mut village = chunk_nbt(-30, 3);
+    let Value::Compound(c) = &mut village else {
+        unreachable!()
+    };
+    c.insert(
+        "structures".into(),
+        fastnbt::nbt!({
+            "starts": {
+                "minecraft:village_plains": {
+                    "id": "minecraft:village_plains",
+                    "ChunkX": -30,
+                    "ChunkZ": 3,
+                    "references": 0,
+                    "Children": [
+                        { "id": "minecraft:jigsaw", "BB": bb([-480, 60, 48, -470, 70, 60]) },
+                        { "id": "minecraft:jigsaw", "BB": bb([-490, 64, 40, -475, 68, 50]) },
+                    ],
+                },
+                "minecraft:monument": { "id": "INVALID" },
+                // Odd entries (i.e. from mods) don't stop the chunk from being parsed
+                "mymod:tower": { "Children": [{ "GD": 0 }] },
+                "mymod:ruin": { "id": "mymod:ruin", "BB": "everywhere" },
+            },
+            "References": {
+                "minecraft:village_plains": Value::LongArray(LongArray::new(vec![
+                    (3i64 << 32) | (-30i64 & 0xffff_ffff),
+                ])),
+            },
+        }),
+    );
+
+    let dim = Dimension::from_iter(
+        None,
+        [
+            // A chunk which fails to parse comes before the village
+            MemoryRegion::new(-1, 0, &[(0, 0, fastnbt::nbt!({})), (2, 3, village)]),
+            MemoryRegion::new(0, 0, &[(0, 0, chunk_nbt(0, 0))]),
+        ]
+        .into_iter(),
+    );
+    assert_eq!(dim.parse_chunks().count(), 3);
+
+    let chunk = dim.get_chunk_in_world(-30, 3).unwrap().unwrap();
+    let tower = &chunk.structures.starts["mymod:tower"];
+    assert_eq!(tower.id, "");
+    assert_eq!(tower.bounding_box(), None);
+    assert!(chunk.structures.unknown_starts.contains_key("mymod:ruin"));
+    assert_eq!(
+        chunk
+            .structures
+            .references("minecraft:village_plains")
+            .collect::<Vec<_>>(),
+        [(-30, 3)]
+    );
+
+    let (villages, errors): (Vec<_>, Vec<_>) = dim
+        .find_structures(|id| id.contains("village"))
+        .partition(Result::is_ok);
+    let villages: Vec<_> = villages.into_iter().map(Result::unwrap).collect();
+    assert_eq!(villages.len(), 1);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        villages[0].bounding_box(),
+        Some(structure::BoundingBox {
+            min_x: -490,
+            min_y: 60,
+            min_z: 40,
+            max_x: -470,
+            max_y: 70,
+            max_z: 60,
+        })
+    );
+
+    // Structures which failed to generate, and those which could not be parsed, are skipped
+    assert_eq!(dim.find_structures(|_| true).flatten().count(), 2);
+}
+
+#[test]
+fn test_find_items() {
+    use fastnbt::Value;
+
+    let elytra = fastnbt::nbt!({ "Slot": 4i8, "id": "minecraft:elytra", "count": 1 });
+
+    let mut chunk = chunk_nbt(1, 2);
+    let Value::Compound(c) = &mut chunk else {
+        unreachable!()
+    };
+    c.insert(
+        "block_entities".into(),
+        fastnbt::nbt!([
+            {
+                "id": "minecraft:chest",
+                "x": 20,
+                "y": 64,
+                "z": 40,
+                "Items": [
+                    { "Slot": 0i8, "id": "minecraft:stone", "count": 64 },
+                    {
+                        "Slot": 1i8,
+                        "id": "minecraft:blue_shulker_box",
+                        "count": 1,
+                        "components": { "minecraft:container": [
+                            { "slot": 7, "item": elytra.clone() },
+                        ] },
+                    },
+                ],
+            },
+            { "id": "minecraft:barrel", "x": 21, "y": 64, "z": 40, "Items": [elytra.clone()] },
+        ]),
+    );
+    // Proto-chunks (and chunks from before 1.17) store their entities in the chunk
+    c.insert(
+        "entities".into(),
+        fastnbt::nbt!([{
+            "id": "minecraft:item",
+            "Pos": [20.5f64, -3.2f64, 41.9f64],
+            "Item": elytra.clone(),
+        }]),
+    );
+
+    let dim = Dimension::from_iter(
+        None,
+        [MemoryRegion::new(0, 0, &[(1, 2, chunk)])].into_iter(),
+    );
+    let is_elytra = |item: &item::ItemStack| item.id == "minecraft:elytra".into();
+    let found: Vec<_> = dim.find_items(is_elytra).map(Result::unwrap).collect();
+    assert_eq!(found.len(), 3);
+
+    let holder = |id: &str, slot| item::ItemHolder {
+        id: id.into(),
+        slot,
+    };
+    assert_eq!(found[0].position, (20, 64, 40));
+    assert_eq!(
+        found[0].path,
+        [holder("chest", None), holder("blue_shulker_box", Some(1))]
+    );
+    assert_eq!(found[0].item.slot, Some(7));
+    assert_eq!(found[1].path, [holder("barrel", None)]);
+    assert_eq!(found[2].position, (20, -4, 41));
+    assert_eq!(found[2].path, [holder("item", None)]);
+
+    // Since 1.17, the entities of full chunks are stored in a separate directory
+    let entity_chunk = fastnbt::nbt!({
+        "DataVersion": EXPECTED_DATA_VERSION,
+        "Position": Value::IntArray(fastnbt::IntArray::new(vec![-1, 0])),
+        "Entities": [
+            {
+                "id": "minecraft:glow_item_frame",
+                "Pos": [-10.5f64, 70.0f64, 3.5f64],
+                "Item": elytra,
+            },
+            { "id": "minecraft:zombie", "Pos": [-12.0f64, 70.0f64, 3.0f64] },
+        ],
+    });
+    let entities = Dimension::from_iter(
+        None,
+        [MemoryRegion::new(-1, 0, &[(31, 0, entity_chunk)])].into_iter(),
+    );
+    let found: Vec<_> = entities
+        .find_entity_items(is_elytra)
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].position, (-11, 70, 3));
+    assert_eq!(found[0].path, [holder("glow_item_frame", None)]);
+
+    // Entity regions can't be parsed as terrain, so each chunk is an error which doesn't stop
+    // the search
+    let results: Vec<_> = entities.find_items(is_elytra).collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[test]
+fn test_find_blocks() {
+    use fastnbt::{LongArray, Value};
+
+    let mut data = vec![0i64; 256];
+    data[0] = 1; // (0, 0, 0)
+    data[17] = 1 << (4 * 3); // index 275 = (3, 1, 1)
+
+    let mut chunk = chunk_nbt(-1, 2);
+    let Value::Compound(c) = &mut chunk else {
+        unreachable!()
+    };
+    c.insert(
+        "sections".into(),
+        fastnbt::nbt!([
+            {
+                "Y": -1i8,
+                "block_states": {
+                    "palette": [{ "Name": "minecraft:air" }, { "Name": "minecraft:spawner" }],
+                    "data": Value::LongArray(LongArray::new(data)),
+                },
+            },
+            {
+                "Y": 0i8,
+                "block_states": { "palette": [{ "Name": "minecraft:spawner" }] },
+            },
+            // The data is invalid, but it is never decoded since nothing in the palette matches
+            {
+                "Y": 1i8,
+                "block_states": {
+                    "palette": [{ "Name": "minecraft:stone" }, { "Name": "minecraft:dirt" }],
+                    "data": Value::LongArray(LongArray::new(vec![0])),
+                },
+            },
+        ]),
+    );
+
+    let dim = Dimension::from_iter(
+        None,
+        [MemoryRegion::new(-1, 0, &[(31, 2, chunk)])].into_iter(),
+    );
+    let spawner = nbt::NamespacedKey::from("spawner");
+    let found: Vec<_> = dim
+        .find_blocks(|state| state.name == spawner)
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(found.len(), 2 + 4096);
+    assert_eq!(found[0], (-16, -16, 32));
+    assert_eq!(found[1], (-13, -15, 33));
+    assert_eq!(found[2], (-16, 0, 32));
+    assert_eq!(found[4097], (-1, 15, 47));
+
+    let mut calls = 0;
+    let bedrock: Vec<_> = dim
+        .find_blocks(|state| {
+            calls += 1;
+            state.name == "minecraft:bedrock".into()
+        })
+        .collect();
+    assert!(bedrock.is_empty());
+    assert_eq!(calls, 5);
+}
+
+#[test]
+fn test_block_stats() {
+    let air = nbt::NamespacedKey::from("air");
+    let ore = nbt::NamespacedKey::from("diamond_ore");
+
+    let mut chunk = empty_chunk(0, 0);
+    chunk.set_block(0, 5, 0, block("diamond_ore"));
+    chunk.set_block(1, 5, 0, block("diamond_ore"));
+    chunk.set_block(0, 6, 0, block("diamond_ore"));
+
+    let mut stats = stats::BlockStats::new();
+    stats.add_chunk(&chunk);
+    assert_eq!(stats.total_blocks(), 2 * 4096);
+    assert_eq!(stats.total(&ore), 3);
+    assert_eq!(stats.total(&air), 2 * 4096 - 3);
+    assert_eq!(stats.count_at(5, &ore), 2);
+    assert_eq!(stats.count_at(6, &ore), 1);
+    assert_eq!(stats.count_at(7, &ore), 0);
+    // The second section is entirely air, so it is counted without data
+    assert_eq!(stats.count_at(20, &air), 256);
+    assert_eq!(stats.ratio_at(5, &ore), Some(2.0 / 256.0));
+    assert_eq!(stats.ratio_at(32, &air), None);
+    assert_eq!(stats.levels().count(), 32);
+    assert_eq!(stats.levels().next().unwrap().0, 0);
+
+    // Counting over a dimension gives the same result as counting each chunk, and chunks which
+    // fail to parse are skipped
+    let dim = Dimension::from_iter(
+        None,
+        [
+            MemoryRegion::new(
+                0,
+                0,
+                &[
+                    (0, 0, chunk_nbt(0, 0)),
+                    (1, 1, fastnbt::nbt!({})),
+                    (5, 5, chunk_nbt(5, 5)),
+                ],
+            ),
+            MemoryRegion::new(-1, 0, &[(0, 0, chunk_nbt(-32, 0))]),
+        ]
+        .into_iter(),
+    );
+    let mut dim_stats = stats::BlockStats::new();
+    dim_stats.add_dimension(&dim);
+    assert_eq!(dim_stats.total(&air), 3 * 4096);
+    assert_eq!(dim_stats.errors(), 1);
+    assert_eq!(dim_stats.totals().len(), 1);
+
+    let region = dim.parse_region(0, 0).unwrap();
+    let mut region_stats = stats::BlockStats::new();
+    region_stats.add_region(&region);
+    assert_eq!(region_stats.total(&air), 2 * 4096);
+    assert_eq!(region_stats.errors(), 1);
+
+    dim_stats.merge(&stats);
+    assert_eq!(dim_stats.total(&air), 5 * 4096 - 3);
+    assert_eq!(dim_stats.count_at(5, &ore), 2);
+    assert_eq!(dim_stats.count_at(5, &air), 3 * 256 + 254);
+    assert_eq!(dim_stats.errors(), 1);
+}
+
+#[test]
+fn test_chunk_status() {
+    use nbt::ChunkStatus;
+
+    assert!(ChunkStatus::Empty < ChunkStatus::Features);
+    assert!(ChunkStatus::Heightmaps < ChunkStatus::Full);
+    assert!(ChunkStatus::ALL.windows(2).all(|w| w[0] < w[1]));
+    for status in ChunkStatus::ALL {
+        assert_eq!(ChunkStatus::from_key(&status.into()), Some(status));
+    }
+    assert_eq!(ChunkStatus::Full.to_string(), "minecraft:full");
+    assert_eq!(
+        ChunkStatus::from_key(&"postprocessed".into()),
+        Some(ChunkStatus::Full)
+    );
+    // Renamed in 1.14, see `ChunkStatusFix2`
+    assert_eq!(
+        ChunkStatus::from_key(&"mobs_spawned".into()),
+        Some(ChunkStatus::Spawn)
+    );
+    assert_eq!(
+        ChunkStatus::from_key(&"base".into()),
+        Some(ChunkStatus::Surface)
+    );
+    assert_eq!(ChunkStatus::from_key(&"mod:full".into()), None);
+
+    let with_status = |x, status: &str| {
+        let mut chunk = chunk_nbt(x, 0);
+        let fastnbt::Value::Compound(c) = &mut chunk else {
+            unreachable!()
+        };
+        c.insert("Status".into(), fastnbt::Value::String(status.into()));
+        (x as u32, 0, chunk)
+    };
+    let dim = Dimension::from_iter(
+        None,
+        [MemoryRegion::new(
+            0,
+            0,
+            &[
+                with_status(0, "minecraft:full"),
+                with_status(1, "minecraft:full"),
+                with_status(2, "minecraft:features"),
+                with_status(3, "minecraft:structure_starts"),
+                with_status(4, "minecraft:sponge"),
+                // A chunk which fails to parse doesn't stop the report
+                (5, 0, fastnbt::nbt!({})),
+            ],
+        )]
+        .into_iter(),
+    );
+
+    let proto: Vec<_> = dim
+        .parse_chunks()
+        .filter_map(|c| c.ok())
+        .filter(|c| c.is_proto_chunk())
+        .map(|c| (c.x_pos, c.chunk_status()))
+        .collect();
+    assert_eq!(
+        proto,
+        [
+            (2, Some(ChunkStatus::Features)),
+            (3, Some(ChunkStatus::StructureStarts)),
+            (4, None)
+        ]
+    );
+
+    let report = stats::StatusReport::from_dimension(&dim);
+    assert_eq!(report.total(), 5);
+    assert_eq!(report.errors(), 1);
+    assert_eq!(report.count(ChunkStatus::Full), 2);
+    assert_eq!(report.count(ChunkStatus::Light), 0);
+    assert_eq!(report.at_least(ChunkStatus::Carvers), 3);
+    assert_eq!(report.proto_chunks(), 3);
+    assert_eq!(report.unknown().get(&"sponge".into()), Some(&1));
+    assert_eq!(
+        report.counts().collect::<Vec<_>>(),
+        [
+            (ChunkStatus::StructureStarts, 1),
+            (ChunkStatus::Features, 1),
+            (ChunkStatus::Full, 2)
+        ]
+    );
 }
@@ -0,0 +1,441 @@
+//! A step-based pipeline for upgrading the contents of old chunks to the current `DataVersion`.
+//!
+//! The structure of old chunks (the `Level` compound, numeric block ids, packed arrays which span
+//! across longs, and MCRegion chunks) is already converted into the current [`ChunkNbt`] layout
+//! when a chunk is parsed (see [`crate::legacy`]).  What's left is the content of the chunk, such
+//! as blocks and biomes which have been renamed since the chunk was saved, which is what the
+//! [`Upgrader`] handles.
+//!
+//! Each [`Step`] upgrades a chunk to a single `DataVersion`, and only the steps which are newer
+//! than the chunk's `DataVersion` are run.  New steps can be added with [`Upgrader::add_step`].
+//!
+//! Chunks from before 1.18 are extended to the height of the overworld since 1.18 (`y = -64` to
+//! `y = 319`), with air in the new sections.  The game only generates terrain below `y = 0`
+//! (`below_zero_retrogen`) for chunks whose `DataVersion` is older than
+//! [`LEVEL_REMOVED_DATA_VERSION`], so upgraded chunks keep air there.  Chunks are assumed to be in
+//! the overworld, since the nether and the end did not change height.
+//!
+//! ```
+//! # use mca_parser::{nbt::ChunkNbt, upgrade::Upgrader};
+//! # fn upgrade(chunk: &mut ChunkNbt) {
+//! Upgrader::default().upgrade(chunk);
+//! assert_eq!(chunk.data_version, mca_parser::upgrade::CURRENT_DATA_VERSION);
+//! # }
+//! ```
+
+use fastnbt::Value;
+
+use crate::{
+    block_entity::BlockEntity,
+    legacy::LEVEL_REMOVED_DATA_VERSION,
+    nbt::{
+        BlockState, ChunkNbt, ChunkSection, HeightMap, NamespacedKey, TickId, TileTick, WorldHeight,
+    },
+};
+
+/// The `DataVersion` that chunks are upgraded to by [`Upgrader::default`] (1.20.4)
+pub const CURRENT_DATA_VERSION: i32 = 3700;
+
+/// A single step in an [`Upgrader`], which upgrades a chunk to `data_version`
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    /// The `DataVersion` that this step upgrades chunks to
+    pub data_version: i32,
+    /// Upgrade a chunk from the `DataVersion` of the previous step
+    pub upgrade: fn(&mut ChunkNbt),
+}
+
+/// A pipeline of [`Step`]s which upgrade chunks to newer `DataVersion`s
+///
+/// [`Upgrader::default`] contains the built-in steps, which upgrade chunks to
+/// [`CURRENT_DATA_VERSION`].
+#[derive(Debug, Clone)]
+pub struct Upgrader {
+    steps: Vec<Step>,
+}
+
+impl Upgrader {
+    /// Create an upgrader without any steps
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// Add a step to this upgrader.  Steps are always run in order of their `data_version`,
+    /// regardless of the order they are added in.
+    pub fn add_step(&mut self, step: Step) {
+        let index = self
+            .steps
+            .partition_point(|s| s.data_version <= step.data_version);
+        self.steps.insert(index, step);
+    }
+
+    /// Get the steps in this upgrader, in the order that they are run
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Get the `DataVersion` that chunks are upgraded to, which is the `DataVersion` of the last
+    /// step.  Returns [`None`] if there are no steps.
+    pub fn target_data_version(&self) -> Option<i32> {
+        self.steps.last().map(|s| s.data_version)
+    }
+
+    /// Run every step which is newer than the `DataVersion` of `chunk`, updating its
+    /// `DataVersion` after each step.
+    ///
+    /// Chunks which are already at (or newer than) the target `DataVersion` are not changed.
+    pub fn upgrade(&self, chunk: &mut ChunkNbt) {
+        for step in &self.steps {
+            if step.data_version > chunk.data_version {
+                (step.upgrade)(chunk);
+                chunk.data_version = step.data_version;
+            }
+        }
+    }
+}
+
+impl Default for Upgrader {
+    fn default() -> Self {
+        let mut upgrader = Self::new();
+        for step in STEPS {
+            upgrader.add_step(*step);
+        }
+
+        upgrader
+    }
+}
+
+/// The built-in steps, see [`Upgrader::default`]
+const STEPS: &[Step] = &[
+    // 1.11
+    Step {
+        data_version: 704,
+        upgrade: |chunk| {
+            // Block entity ids were namespaced and some were renamed
+            for be in &mut chunk.block_entities {
+                let BlockEntity::Unknown(Value::Compound(value)) = be else {
+                    continue;
+                };
+                let Some(Value::String(id)) = value.get("id") else {
+                    continue;
+                };
+                if let Some((_, to)) = BLOCK_ENTITY_IDS.iter().find(|(from, _)| from == id) {
+                    value.insert("id".into(), Value::String(format!("minecraft:{}", to)));
+                    *be = BlockEntity::from_value(Value::Compound(std::mem::take(value)));
+                }
+            }
+        },
+    },
+    // 1.13
+    Step {
+        data_version: 1451,
+        upgrade: |chunk| {
+            // Ticks use the names of the blocks since the flattening, and fluids have their own
+            // list of ticks
+            let (fluid_ticks, block_ticks) = std::mem::take(&mut chunk.block_ticks)
+                .into_iter()
+                .map(flatten_tick)
+                .partition(|tick| is_fluid(&tick.id));
+            chunk.block_ticks = block_ticks;
+            chunk.fluid_ticks.extend(fluid_ticks);
+        },
+    },
+    // 1.14
+    Step {
+        data_version: 1952,
+        upgrade: |chunk| {
+            rename_blocks(
+                chunk,
+                &[
+                    ("sign", "oak_sign"),
+                    ("wall_sign", "oak_wall_sign"),
+                    ("stone_slab", "smooth_stone_slab"),
+                ],
+            );
+
+            // The names of the statuses were changed
+            if let Some(status) = chunk.chunk_status() {
+                chunk.status = status.into();
+            }
+
+            // The lighting engine was rewritten, so the old light is recalculated by the game
+            chunk.is_light_on = None;
+        },
+    },
+    // 1.17
+    Step {
+        data_version: 2724,
+        upgrade: |chunk| {
+            rename_blocks(chunk, &[("grass_path", "dirt_path")]);
+
+            // Cauldrons with water in them became a separate block
+            for_each_block_state(chunk, |state| {
+                if is_vanilla(state, "cauldron") {
+                    match state.get_int("level") {
+                        Some(0) | None => state.properties.clear(),
+                        Some(_) => state.name = NamespacedKey::minecraft("water_cauldron".into()),
+                    }
+                }
+            });
+        },
+    },
+    // 1.18
+    Step {
+        data_version: LEVEL_REMOVED_DATA_VERSION,
+        upgrade: extend_height,
+    },
+    Step {
+        data_version: 2860,
+        upgrade: |chunk| {
+            rename_biomes(
+                chunk,
+                &[
+                    ("badlands_plateau", "badlands"),
+                    ("bamboo_jungle_hills", "bamboo_jungle"),
+                    ("birch_forest_hills", "birch_forest"),
+                    ("dark_forest_hills", "dark_forest"),
+                    ("deep_warm_ocean", "warm_ocean"),
+                    ("desert_hills", "desert"),
+                    ("desert_lakes", "desert"),
+                    ("giant_spruce_taiga", "old_growth_spruce_taiga"),
+                    ("giant_spruce_taiga_hills", "old_growth_spruce_taiga"),
+                    ("giant_tree_taiga", "old_growth_pine_taiga"),
+                    ("giant_tree_taiga_hills", "old_growth_pine_taiga"),
+                    ("gravelly_mountains", "windswept_gravelly_hills"),
+                    ("jungle_edge", "sparse_jungle"),
+                    ("jungle_hills", "jungle"),
+                    ("modified_badlands_plateau", "badlands"),
+                    ("modified_gravelly_mountains", "windswept_gravelly_hills"),
+                    ("modified_jungle", "jungle"),
+                    ("modified_jungle_edge", "sparse_jungle"),
+                    ("modified_wooded_badlands_plateau", "wooded_badlands"),
+                    ("mountain_edge", "windswept_hills"),
+                    ("mountains", "windswept_hills"),
+                    ("mushroom_field_shore", "mushroom_fields"),
+                    ("shattered_savanna", "windswept_savanna"),
+                    ("shattered_savanna_plateau", "windswept_savanna"),
+                    ("snowy_mountains", "snowy_plains"),
+                    ("snowy_taiga_hills", "snowy_taiga"),
+                    ("snowy_taiga_mountains", "snowy_taiga"),
+                    ("snowy_tundra", "snowy_plains"),
+                    ("stone_shore", "stony_shore"),
+                    ("swamp_hills", "swamp"),
+                    ("taiga_hills", "taiga"),
+                    ("taiga_mountains", "taiga"),
+                    ("tall_birch_forest", "old_growth_birch_forest"),
+                    ("tall_birch_hills", "old_growth_birch_forest"),
+                    ("wooded_badlands_plateau", "wooded_badlands"),
+                    ("wooded_hills", "forest"),
+                    ("wooded_mountains", "windswept_forest"),
+                ],
+            );
+        },
+    },
+    // 1.20.3
+    Step {
+        data_version: 3698,
+        upgrade: |chunk| rename_blocks(chunk, &[("grass", "short_grass")]),
+    },
+    // 1.20.4
+    Step {
+        data_version: CURRENT_DATA_VERSION,
+        upgrade: |_| {},
+    },
+];
+
+/// The ids of block entities before 1.11, and the key of their namespaced id since 1.11
+const BLOCK_ENTITY_IDS: &[(&str, &str)] = &[
+    ("Airportal", "end_portal"),
+    ("Banner", "banner"),
+    ("Beacon", "beacon"),
+    ("Cauldron", "brewing_stand"),
+    ("Chest", "chest"),
+    ("Comparator", "comparator"),
+    ("Control", "command_block"),
+    ("DLDetector", "daylight_detector"),
+    ("Dropper", "dropper"),
+    ("EnchantTable", "enchanting_table"),
+    ("EndGateway", "end_gateway"),
+    ("EnderChest", "ender_chest"),
+    ("FlowerPot", "flower_pot"),
+    ("Furnace", "furnace"),
+    ("Hopper", "hopper"),
+    ("MobSpawner", "mob_spawner"),
+    ("Music", "noteblock"),
+    ("Piston", "piston"),
+    ("RecordPlayer", "jukebox"),
+    ("Sign", "sign"),
+    ("Skull", "skull"),
+    ("Structure", "structure_block"),
+    ("Trap", "dispenser"),
+];
+
+/// The names of blocks which are commonly ticked before 1.13, and their names since 1.13
+const TICK_RENAMES: &[(&str, &str)] = &[
+    ("flowing_water", "water"),
+    ("flowing_lava", "lava"),
+    ("unpowered_repeater", "repeater"),
+    ("powered_repeater", "repeater"),
+    ("unpowered_comparator", "comparator"),
+    ("powered_comparator", "comparator"),
+    ("unlit_redstone_torch", "redstone_torch"),
+    ("lit_redstone_lamp", "redstone_lamp"),
+    ("wooden_button", "oak_button"),
+    ("wooden_pressure_plate", "oak_pressure_plate"),
+    ("reeds", "sugar_cane"),
+];
+
+/// Convert a tick from before 1.13 to use the name of its block since 1.13
+fn flatten_tick(mut tick: TileTick) -> TileTick {
+    let mut name = tick.id.name();
+    if let Some((_, to)) = TICK_RENAMES
+        .iter()
+        .find(|(from, _)| name == NamespacedKey::minecraft(from.to_string()))
+    {
+        name = NamespacedKey::minecraft(to.to_string());
+    }
+
+    tick.id = TickId::Name(name);
+    tick
+}
+
+/// Check if a tick is for a vanilla fluid
+fn is_fluid(id: &TickId) -> bool {
+    ["water", "lava"]
+        .iter()
+        .any(|fluid| id.name() == NamespacedKey::minecraft(fluid.to_string()))
+}
+
+/// The height of the overworld before 1.18
+const OLD_OVERWORLD: WorldHeight = WorldHeight {
+    min_y: 0,
+    height: 256,
+};
+
+/// Extend a chunk from before 1.18 to the height of the overworld since 1.18, filling the new
+/// sections with air and the biomes of the nearest section
+fn extend_height(chunk: &mut ChunkNbt) {
+    if chunk.y_pos != 0 {
+        return;
+    }
+
+    let old_min = OLD_OVERWORLD.min_y / 16;
+    let old_max = old_min + OLD_OVERWORLD.height as i32 / 16 - 1;
+    let new_min = WorldHeight::OVERWORLD.min_y / 16;
+    let new_max = new_min + WorldHeight::OVERWORLD.height as i32 / 16 - 1;
+
+    let biomes_at = |chunk: &ChunkNbt, y: i32| {
+        chunk
+            .sections
+            .iter()
+            .find(|s| s.y as i32 == y)
+            .and_then(|s| s.biomes.clone())
+    };
+    let (bottom_biomes, top_biomes) = (biomes_at(chunk, old_min), biomes_at(chunk, old_max));
+
+    // Sections outside of the old height only stored light, so they are replaced
+    chunk
+        .sections
+        .retain(|s| (old_min..=old_max).contains(&(s.y as i32)));
+    for y in new_min..=new_max {
+        if chunk.sections.iter().any(|s| s.y as i32 == y) {
+            continue;
+        }
+
+        let mut section = ChunkSection::new(y as i8);
+        section.biomes = if y < old_min {
+            bottom_biomes.clone()
+        } else {
+            top_biomes.clone()
+        };
+        chunk.sections.push(section);
+    }
+    chunk.sections.sort_by_key(|s| s.y);
+    chunk.y_pos = new_min;
+
+    // Heightmaps store the height above the bottom of the world, which has moved
+    let hm = &mut chunk.height_maps;
+    for height_map in [
+        &mut hm.motion_blocking,
+        &mut hm.motion_blocking_no_leaves,
+        &mut hm.ocean_floor,
+        &mut hm.ocean_floor_wg,
+        &mut hm.world_surface,
+        &mut hm.world_surface_wg,
+    ] {
+        *height_map = height_map.as_ref().and_then(extend_height_map);
+    }
+
+    // Post-processing is stored for each section
+    let below = (old_min - new_min) as usize;
+    let above = (new_max - old_max) as usize;
+    chunk
+        .post_processing
+        .resize((old_max - old_min + 1) as usize, Vec::new());
+    let mut post_processing = vec![Vec::new(); below];
+    post_processing.append(&mut chunk.post_processing);
+    post_processing.resize(post_processing.len() + above, Vec::new());
+    chunk.post_processing = post_processing;
+
+    // The new sections have no light
+    chunk.is_light_on = None;
+}
+
+/// Convert a heightmap from the height of the overworld before 1.18 to the height since 1.18.
+/// Returns [`None`] if the heightmap is not valid, so that the game recalculates it.
+fn extend_height_map(height_map: &HeightMap) -> Option<HeightMap> {
+    let mut heights = [0; 256];
+    for (i, height) in heights.iter_mut().enumerate() {
+        let old = height_map.get_height(i as u32 % 16, i as u32 / 16, OLD_OVERWORLD)?;
+        // Columns without blocks are still empty, since the new sections are air
+        *height = if old < OLD_OVERWORLD.min_y {
+            WorldHeight::OVERWORLD.min_y - 1
+        } else {
+            old
+        };
+    }
+
+    Some(HeightMap::new(&heights, WorldHeight::OVERWORLD))
+}
+
+/// Check if `state` is the vanilla block `name`
+fn is_vanilla(state: &BlockState, name: &str) -> bool {
+    state.name == NamespacedKey::minecraft(name.into())
+}
+
+/// Run `f` on every block state in the palettes of `chunk`, then remove any duplicates that it
+/// created
+fn for_each_block_state(chunk: &mut ChunkNbt, mut f: impl FnMut(&mut BlockState)) {
+    for section in &mut chunk.sections {
+        if let Some(block_states) = &mut section.block_states {
+            block_states.palette.iter_mut().for_each(&mut f);
+            block_states.normalize();
+        }
+    }
+}
+
+/// Rename vanilla blocks from the first name of each pair to the second, keeping their properties
+fn rename_blocks(chunk: &mut ChunkNbt, renames: &[(&str, &str)]) {
+    for_each_block_state(chunk, |state| {
+        if let Some((_, to)) = renames.iter().find(|(from, _)| is_vanilla(state, from)) {
+            state.name = NamespacedKey::minecraft(to.to_string());
+        }
+    });
+}
+
+/// Rename vanilla biomes from the first name of each pair to the second
+fn rename_biomes(chunk: &mut ChunkNbt, renames: &[(&str, &str)]) {
+    for section in &mut chunk.sections {
+        let Some(biomes) = &mut section.biomes else {
+            continue;
+        };
+
+        for biome in &mut biomes.palette {
+            let key = biome.strip_prefix("minecraft:").unwrap_or(biome);
+            if let Some((_, to)) = renames.iter().find(|(from, _)| *from == key) {
+                *biome = format!("minecraft:{}", to);
+            }
+        }
+        biomes.normalize();
+    }
+}
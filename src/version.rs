@@ -0,0 +1,118 @@
+//! A lookup table between `DataVersion`s and the names of the releases of the game that they
+//! belong to.
+//!
+//! Only releases since 1.9 (the first release to save a `DataVersion`) are included, snapshots
+//! are not.
+//!
+//! ```
+//! # use mca_parser::version;
+//! assert_eq!(version::release_name(3700), Some("1.20.4"));
+//! assert_eq!(version::release_data_version("1.20.4"), Some(3700));
+//! // 22w42a is a snapshot between 1.19.2 and 1.19.3
+//! assert_eq!(version::latest_release_at(3205), Some("1.19.2"));
+//! ```
+//!
+//! - See <https://minecraft.wiki/w/Data_version>
+
+/// The `DataVersion` and name of every release since 1.9, in order
+pub const RELEASES: &[(i32, &str)] = &[
+    (169, "1.9"),
+    (175, "1.9.1"),
+    (176, "1.9.2"),
+    (183, "1.9.3"),
+    (184, "1.9.4"),
+    (510, "1.10"),
+    (511, "1.10.1"),
+    (512, "1.10.2"),
+    (819, "1.11"),
+    (921, "1.11.1"),
+    (922, "1.11.2"),
+    (1139, "1.12"),
+    (1241, "1.12.1"),
+    (1343, "1.12.2"),
+    (1519, "1.13"),
+    (1628, "1.13.1"),
+    (1631, "1.13.2"),
+    (1952, "1.14"),
+    (1957, "1.14.1"),
+    (1963, "1.14.2"),
+    (1968, "1.14.3"),
+    (1976, "1.14.4"),
+    (2225, "1.15"),
+    (2227, "1.15.1"),
+    (2230, "1.15.2"),
+    (2566, "1.16"),
+    (2567, "1.16.1"),
+    (2578, "1.16.2"),
+    (2580, "1.16.3"),
+    (2584, "1.16.4"),
+    (2586, "1.16.5"),
+    (2724, "1.17"),
+    (2730, "1.17.1"),
+    (2860, "1.18"),
+    (2865, "1.18.1"),
+    (2975, "1.18.2"),
+    (3105, "1.19"),
+    (3117, "1.19.1"),
+    (3120, "1.19.2"),
+    (3218, "1.19.3"),
+    (3337, "1.19.4"),
+    (3463, "1.20"),
+    (3465, "1.20.1"),
+    (3578, "1.20.2"),
+    (3698, "1.20.3"),
+    (3700, "1.20.4"),
+    (3837, "1.20.5"),
+    (3839, "1.20.6"),
+    (3953, "1.21"),
+    (3955, "1.21.1"),
+    (4080, "1.21.2"),
+    (4082, "1.21.3"),
+    (4189, "1.21.4"),
+    (4325, "1.21.5"),
+    (4435, "1.21.6"),
+    (4438, "1.21.7"),
+    (4440, "1.21.8"),
+];
+
+/// Get the name of the release with exactly this `DataVersion`, returns [`None`] for snapshots
+/// and unknown versions
+pub fn release_name(data_version: i32) -> Option<&'static str> {
+    RELEASES
+        .binary_search_by_key(&data_version, |(v, _)| *v)
+        .ok()
+        .map(|i| RELEASES[i].1)
+}
+
+/// Get the `DataVersion` of a release from its name (i.e. `"1.20.4"`)
+pub fn release_data_version(name: &str) -> Option<i32> {
+    RELEASES.iter().find(|(_, n)| *n == name).map(|(v, _)| *v)
+}
+
+/// Get the name of the newest release with a `DataVersion` that is less than or equal to
+/// `data_version`, so that chunks saved by snapshots give the release before them.
+///
+/// Returns [`None`] if `data_version` is older than 1.9.
+pub fn latest_release_at(data_version: i32) -> Option<&'static str> {
+    let index = RELEASES.partition_point(|(v, _)| *v <= data_version);
+    index.checked_sub(1).map(|i| RELEASES[i].1)
+}
+
+#[test]
+fn test_releases_sorted() {
+    assert!(RELEASES.windows(2).all(|w| w[0].0 < w[1].0));
+}
+
+#[test]
+fn test_lookup() {
+    assert_eq!(release_name(3700), Some("1.20.4"));
+    assert_eq!(release_name(3120), Some("1.19.2"));
+    assert_eq!(release_name(3205), None);
+    assert_eq!(release_data_version("1.19.2"), Some(3120));
+    assert_eq!(release_data_version("1.19.5"), None);
+
+    assert_eq!(latest_release_at(3700), Some("1.20.4"));
+    assert_eq!(latest_release_at(3699), Some("1.20.3"));
+    assert_eq!(latest_release_at(100_000), Some("1.21.8"));
+    assert_eq!(latest_release_at(100), None);
+}